@@ -0,0 +1,412 @@
+//! ATI (Automatic Tuning Implementation): supervised automatic ATI (`Iqs231::run_ati()`) and a
+//! manual procedure for electrodes the device can not tune itself (`Iqs231::manual_ati()`).
+//!
+//! ## Manual ATI
+//!
+//! The device switches off automatic ATI (`UiFlags::UI_AUTO_ATI_OFF`) once the multipliers or
+//! compensation are written over I²C. `Iqs231::manual_ati()` then searches the settings in two
+//! steps, like the device does:
+//!
+//! 1. with compensation 0, the sensitivity multiplier giving the count closest to the `BaseValue`
+//! 2. the smallest compensation multiplier for which the target count is reachable, and the
+//!    compensation (binary search) bringing the filtered count closest to the target
+//!
+//! Every setting is given `settle_ms` for the filtered count to follow before it is read.
+use crate::{
+    interface::{Delay, I2cInterface},
+    registers::{
+        AtiFlags, BaseValue, ChannelMultiplier, EventFlags, Register, SystemFlags, UiFlags,
+    },
+    Error, Iqs231,
+};
+
+/// Interval at which `Iqs231::run_ati()` polls the ATI status
+pub const ATI_POLL_MS: u32 = 10;
+
+/// Why automatic ATI failed, see `AtiOutcome::Error`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AtiErrorCause {
+    /// `CH0_ATI_ERROR`
+    pub event_flags: EventFlags,
+    /// Which channel saturated its compensation, and in which direction
+    pub ati_flags: AtiFlags,
+    /// Time until the device retries ATI by itself (`TimerRedoAti`)
+    pub redo_in_ms: u32,
+}
+
+/// Result of `Iqs231::run_ati()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AtiOutcome {
+    /// ATI completed, with the resulting CH0 settings
+    Success {
+        multipliers: ChannelMultiplier,
+        compensation: u8,
+        elapsed_ms: u32,
+    },
+    /// The device flagged an ATI error
+    Error { cause: AtiErrorCause },
+    /// ATI still running when the timeout expired
+    Timeout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AtiChannel {
+    /// Proximity channel
+    Ch0,
+    /// Movement channel
+    Ch1,
+}
+
+/// Settings for `Iqs231::manual_ati()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ManualAti {
+    pub base: BaseValue,
+    /// Filtered count to reach with compensation
+    pub target: u16,
+    /// Accepted difference between the filtered count and `target` (default 8)
+    pub tolerance: u16,
+    /// Time to wait after changing a setting before reading the count (default 500ms)
+    pub settle_ms: u32,
+}
+
+impl ManualAti {
+    pub fn new(base: BaseValue, target: u16) -> Self {
+        Self {
+            base,
+            target,
+            tolerance: 8,
+            settle_ms: 500,
+        }
+    }
+
+    pub fn with_tolerance(self, tolerance: u16) -> Self {
+        Self { tolerance, ..self }
+    }
+
+    pub fn with_settle_ms(self, settle_ms: u32) -> Self {
+        Self { settle_ms, ..self }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ManualAtiStatus {
+    /// Filtered count within tolerance of the target
+    Settled,
+    /// Count above the target even without compensation (too much signal)
+    CompensationMin,
+    /// Target not reachable with maximum compensation (too little signal)
+    CompensationMax,
+    /// Target in range, but the closest compensation is outside the tolerance
+    OutOfTolerance,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ManualAtiResult {
+    pub channel: AtiChannel,
+    pub status: ManualAtiStatus,
+    /// Final (written) multipliers
+    pub multipliers: ChannelMultiplier,
+    /// Final (written) compensation
+    pub compensation: u8,
+    /// Filtered count achieved with the final settings
+    pub count: u16,
+    /// `UiFlags::UI_AUTO_ATI_OFF` after tuning. If not set, the device may redo ATI and
+    /// overwrite the result.
+    pub auto_ati_off: bool,
+}
+
+impl<E, I> Iqs231<I>
+where
+    I: I2cInterface<Error = E>,
+{
+    /// Start ATI on CH0 (see `trigger_ati()`, failing with `Error::ModeChangeNotConfirmed` if the
+    /// device did not start it) and wait for it to complete, polling every `ATI_POLL_MS` for at
+    /// most `timeout_ms`. ATI is done when neither `SystemFlags::ATI_MODE` nor
+    /// `AtiFlags::CH0_ATI_BUSY` is set; `EventFlags::CH0_ATI_ERROR` ends it with an error.
+    pub fn run_ati<D: Delay>(
+        &mut self,
+        delay: &mut D,
+        timeout_ms: u32,
+    ) -> Result<AtiOutcome, Error<E>> {
+        self.trigger_ati()?;
+
+        let mut elapsed_ms = 0;
+        while elapsed_ms < timeout_ms {
+            delay.delay_ms(ATI_POLL_MS);
+            elapsed_ms = elapsed_ms.saturating_add(ATI_POLL_MS);
+
+            let [system, _ui, ati, events] = self.read_burst::<4>(Register::System_Flags)?.value;
+            let system = SystemFlags::from_bits_retain(system);
+            let ati_flags = AtiFlags::from_bits_retain(ati);
+            let event_flags = EventFlags::from_bits_retain(events) & EventFlags::CH0_ATI_ERROR;
+
+            if !event_flags.is_empty() {
+                let redo_in_ms = self.get_timer_redo_ati()?.value as u32 * 100;
+                return Ok(ati_outcome(AtiOutcome::Error {
+                    cause: AtiErrorCause {
+                        event_flags,
+                        ati_flags,
+                        redo_in_ms,
+                    },
+                }));
+            }
+
+            if !system.contains(SystemFlags::ATI_MODE)
+                && !ati_flags.contains(AtiFlags::CH0_ATI_BUSY)
+            {
+                return Ok(ati_outcome(AtiOutcome::Success {
+                    multipliers: self.get_ch0_multipliers()?.value,
+                    compensation: self.get_ch0_compensation()?.value,
+                    elapsed_ms,
+                }));
+            }
+        }
+
+        Ok(ati_outcome(AtiOutcome::Timeout))
+    }
+
+    /// Tune `channel` manually, see the module documentation. The final settings are left
+    /// written to the device.
+    pub fn manual_ati<D: Delay>(
+        &mut self,
+        channel: AtiChannel,
+        settings: &ManualAti,
+        delay: &mut D,
+    ) -> Result<ManualAtiResult, Error<E>> {
+        let base = settings.base.counts();
+        let target = settings.target;
+
+        // 1. sensitivity multiplier closest to the base value (counts increase with the multiplier)
+        let mut best = (ChannelMultiplier::new(), u16::MAX);
+        for sensitivity in 0..=3 {
+            let mult = ChannelMultiplier::new().with_sensitivity_multiplier(sensitivity);
+            let count = self.ati_measure(channel, mult, 0, settings, delay)?;
+            if count.abs_diff(base) < best.1.abs_diff(base) {
+                best = (mult, count);
+            }
+            if count >= base {
+                break;
+            }
+        }
+        let (mut mult, uncompensated) = best;
+
+        // 2. compensation
+        let (status, compensation) = if uncompensated > target.saturating_add(settings.tolerance) {
+            (ManualAtiStatus::CompensationMin, 0)
+        } else {
+            let mut reachable = false;
+            for compensation_multiplier in 0..=15 {
+                mult.set_compensation_multiplier(compensation_multiplier);
+                let max = self.ati_measure(channel, mult, 255, settings, delay)?;
+                if max.saturating_add(settings.tolerance) >= target {
+                    reachable = true;
+                    break;
+                }
+            }
+
+            if reachable {
+                let compensation = self.ati_search(channel, mult, settings, delay)?;
+                (ManualAtiStatus::Settled, compensation)
+            } else {
+                (ManualAtiStatus::CompensationMax, 255)
+            }
+        };
+
+        let count = self.ati_measure(channel, mult, compensation, settings, delay)?;
+        let status = match status {
+            ManualAtiStatus::Settled if count.abs_diff(target) > settings.tolerance => {
+                ManualAtiStatus::OutOfTolerance
+            }
+            status => status,
+        };
+
+        let result = ManualAtiResult {
+            channel,
+            status,
+            multipliers: mult,
+            compensation,
+            count,
+            auto_ati_off: self.get_ui_flags()?.contains(UiFlags::UI_AUTO_ATI_OFF),
+        };
+
+        match result.status {
+            ManualAtiStatus::Settled => info!("Manual ATI: {:?}", result),
+            _ => warn!("Manual ATI: {:?}", result),
+        }
+
+        Ok(result)
+    }
+
+    /// Compensation bringing the count closest to the target (count increases with compensation)
+    fn ati_search<D: Delay>(
+        &mut self,
+        channel: AtiChannel,
+        mult: ChannelMultiplier,
+        settings: &ManualAti,
+        delay: &mut D,
+    ) -> Result<u8, Error<E>> {
+        // smallest compensation reaching the target
+        let (mut low, mut high) = (0u16, 255u16);
+        let mut high_count = None;
+        while low < high {
+            let mid = (low + high) / 2;
+            let count = self.ati_measure(channel, mult, mid as u8, settings, delay)?;
+            if count >= settings.target {
+                high = mid;
+                high_count = Some(count);
+            } else {
+                low = mid + 1;
+            }
+        }
+
+        if high == 0 {
+            return Ok(0);
+        }
+        let high_count = match high_count {
+            Some(count) => count,
+            None => self.ati_measure(channel, mult, high as u8, settings, delay)?,
+        };
+        let below = self.ati_measure(channel, mult, high as u8 - 1, settings, delay)?;
+
+        if below.abs_diff(settings.target) < high_count.abs_diff(settings.target) {
+            Ok(high as u8 - 1)
+        } else {
+            Ok(high as u8)
+        }
+    }
+
+    /// Write the settings, let the filtered count settle and read it
+    fn ati_measure<D: Delay>(
+        &mut self,
+        channel: AtiChannel,
+        mult: ChannelMultiplier,
+        compensation: u8,
+        settings: &ManualAti,
+        delay: &mut D,
+    ) -> Result<u16, Error<E>> {
+        let count = match channel {
+            AtiChannel::Ch0 => {
+                self.set_ch0_multipliers(mult)?;
+                self.set_ch0_compensation(compensation)?;
+                delay.delay_ms(settings.settle_ms);
+                self.get_prox_filtered_count()?
+            }
+            AtiChannel::Ch1 => {
+                self.set_ch1_multipliers(mult)?;
+                self.set_ch1_compensation(compensation)?;
+                delay.delay_ms(settings.settle_ms);
+                self.get_move_filtered_count()?
+            }
+        };
+        Ok(count.value)
+    }
+}
+
+/// Log the outcome of `Iqs231::run_ati()`
+fn ati_outcome(outcome: AtiOutcome) -> AtiOutcome {
+    match outcome {
+        AtiOutcome::Success { .. } => info!("ATI: {:?}", outcome),
+        _ => warn!("ATI: {:?}", outcome),
+    }
+    outcome
+}
+
+#[cfg(test)]
+use crate::sim::{self, SensorModel};
+
+#[test]
+fn manual_ati_reaches_target() {
+    let mut model = SensorModel::new(0);
+    model.electrode = Some(40);
+    let (sim, mut iqs) = sim::test_device(Some(model));
+    let mut delay = sim.clone();
+
+    let settings = ManualAti::new(BaseValue::Counts150, 800);
+    let result = iqs
+        .manual_ati(AtiChannel::Ch0, &settings, &mut delay)
+        .unwrap();
+    assert_eq!(result.status, ManualAtiStatus::Settled);
+    assert_eq!(result.multipliers.sensitivity_multiplier(), 2);
+    assert!(result.count.abs_diff(800) <= 8);
+    assert!(result.auto_ati_off);
+    assert_eq!(
+        sim.register(Register::CH0_Compensation),
+        result.compensation
+    );
+
+    let settings = ManualAti::new(BaseValue::Counts150, 100);
+    let result = iqs
+        .manual_ati(AtiChannel::Ch0, &settings, &mut delay)
+        .unwrap();
+    assert_eq!(result.status, ManualAtiStatus::CompensationMin);
+}
+
+#[test]
+fn run_ati_reports_outcome() {
+    let (sim, mut iqs) = sim::test_device(None);
+    let mut delay = sim.clone();
+    assert_eq!(iqs.run_ati(&mut delay, 50).unwrap(), AtiOutcome::Timeout);
+    // let the timed out ATI finish, `run_ati()` would wait on it instead of starting another
+    sim.advance(sim::ATI_DURATION_MS);
+
+    let mut model = SensorModel::new(800);
+    model.electrode = Some(40);
+    sim.attach_model(model);
+    match iqs.run_ati(&mut delay, 1000).unwrap() {
+        AtiOutcome::Success {
+            multipliers,
+            compensation,
+            elapsed_ms,
+        } => {
+            assert!(sim::compensated_count(40, multipliers, compensation) >= 800);
+            assert!(elapsed_ms >= sim::ATI_DURATION_MS);
+        }
+        outcome => panic!("unexpected {:?}", outcome),
+    }
+
+    model.electrode = Some(1);
+    sim.attach_model(model);
+    match iqs.run_ati(&mut delay, 1000).unwrap() {
+        AtiOutcome::Error { cause } => {
+            assert!(cause.event_flags.contains(EventFlags::CH0_ATI_ERROR));
+            assert!(cause.ati_flags.contains(AtiFlags::CH0_COMPENSATION_MAX));
+            assert_eq!(
+                cause.redo_in_ms,
+                sim.register(Register::TimerRedoAti) as u32 * 100
+            );
+        }
+        outcome => panic!("unexpected {:?}", outcome),
+    }
+}
+
+#[test]
+fn run_ati_requires_ati_to_start() {
+    let (sim, mut iqs) = sim::test_device(None);
+    let mut delay = sim.clone();
+
+    sim.set_ignore_commands(true);
+    assert!(matches!(
+        iqs.run_ati(&mut delay, 1000),
+        Err(Error::ModeChangeNotConfirmed)
+    ));
+}
+
+#[test]
+fn run_ati_ignores_stale_ch1_error() {
+    let mut model = SensorModel::new(800);
+    model.electrode = Some(40);
+    let (sim, mut iqs) = sim::test_device(Some(model));
+    let mut delay = sim.clone();
+
+    sim.set_event_flags(EventFlags::CH1_ATI_ERROR);
+    assert!(matches!(
+        iqs.run_ati(&mut delay, 1000).unwrap(),
+        AtiOutcome::Success { .. }
+    ));
+}
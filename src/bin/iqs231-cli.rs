@@ -0,0 +1,39 @@
+//! Bring-up tool for the IQS231 on Linux (Raspberry Pi and friends), see `iqs231::cli`
+use std::{env, io, process::ExitCode, thread, time::Duration};
+
+use iqs231::{cli, Iqs231};
+use linux_embedded_hal::I2cdev;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let invocation = match cli::parse_args(&args) {
+        Ok(invocation) => invocation,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    let bus = match I2cdev::new(&invocation.bus) {
+        Ok(bus) => bus,
+        Err(e) => {
+            eprintln!("cannot open {}: {}", invocation.bus, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut iqs = Iqs231::new(bus).with_address(invocation.address);
+    let sleep = |ms| thread::sleep(Duration::from_millis(ms as u64));
+    match cli::run(
+        &mut iqs,
+        invocation.command,
+        &mut io::stdout().lock(),
+        sleep,
+    ) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {:?}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! Command line front end used by the `iqs231-cli` binary (feature `cli`).
+//!
+//! The logic is generic over the bus so it can be exercised against `SimIqs231` without
+//! hardware; the binary only opens the Linux `I2cdev` and calls `run()`.
+//!
+//! ```text
+//! iqs231-cli [--bus /dev/i2c-1] [--address 0x44] <command>
+//!
+//!   probe                        product number and software version
+//!   dump                         all registers, decoded
+//!   get <register>               single register (name or address)
+//!   set <register> <value>       single writable register (name or address)
+//!   send <command>[|<command>]   Commands, e.g. `ATI_CH0` or `TOGGLE_AC_FILTER | TOGGLE_ULP_MODE`
+//!   stream [<samples>] [<ms>]    counts every <ms> (default 100), forever without <samples>
+//! ```
+//!
+//! `stream` writes CSV (see `sampling::CSV_HEADER`) to stdout.
+use std::{
+    fmt, io,
+    string::{String, ToString},
+};
+
+use crate::{
+    device::I2cAddress,
+    interface::I2cInterface,
+    registers::{Commands, Register, REGISTER_COUNT},
+    sampling::{self, CountSample},
+    Error, Iqs231,
+};
+
+pub const DEFAULT_BUS: &str = "/dev/i2c-1";
+
+pub const USAGE: &str = "\
+usage: iqs231-cli [--bus <device>] [--address <0x44..0x47>] <command>
+
+commands:
+  probe                        product number and software version
+  dump                         all registers, decoded
+  get <register>               single register (name or address)
+  set <register> <value>       single writable register (name or address)
+  send <command>[|<command>]   e.g. ATI_CH0, DISABLE_SENSING, TOGGLE_AC_FILTER|TOGGLE_ULP_MODE
+  stream [<samples>] [<ms>]    counts every <ms> (default 100), forever without <samples>";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Probe,
+    Dump,
+    Get(Register),
+    Set(Register, u8),
+    Send(Commands),
+    Stream {
+        samples: Option<u32>,
+        period_ms: u32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    pub bus: String,
+    pub address: I2cAddress,
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageError(pub String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n\n{}", self.0, USAGE)
+    }
+}
+
+#[derive(Debug)]
+pub enum CliError<E> {
+    Device(Error<E>),
+    Output(io::Error),
+}
+
+impl<E> From<Error<E>> for CliError<E> {
+    fn from(e: Error<E>) -> Self {
+        CliError::Device(e)
+    }
+}
+
+impl<E> From<io::Error> for CliError<E> {
+    fn from(e: io::Error) -> Self {
+        CliError::Output(e)
+    }
+}
+
+/// Parse the arguments (without the program name)
+pub fn parse_args<S: AsRef<str>>(args: &[S]) -> Result<Invocation, UsageError> {
+    let mut args = args.iter().map(AsRef::as_ref);
+    let mut bus = String::from(DEFAULT_BUS);
+    let mut address = I2cAddress::default();
+
+    let name = loop {
+        match args.next() {
+            Some("--bus") => {
+                bus = args
+                    .next()
+                    .ok_or_else(|| usage("--bus needs a device"))?
+                    .into()
+            }
+            Some("--address") => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| usage("--address needs a value"))?;
+                address = parse_u8(value)
+                    .and_then(|a| I2cAddress::try_from(a).ok())
+                    .ok_or_else(|| usage("address should be one of 0x44..0x47"))?;
+            }
+            Some(name) => break name,
+            None => return Err(usage("missing command")),
+        }
+    };
+
+    let command = match name {
+        "probe" => Command::Probe,
+        "dump" => Command::Dump,
+        "get" => Command::Get(parse_register(args.next())?),
+        "set" => {
+            let register = parse_register(args.next())?;
+            let value = args
+                .next()
+                .and_then(parse_u8)
+                .ok_or_else(|| usage("set needs a value (0..=255)"))?;
+            Command::Set(register, value)
+        }
+        "send" => {
+            let commands = args.by_ref().collect::<std::vec::Vec<_>>().join(" ");
+            let commands = bitflags::parser::from_str::<Commands>(&commands)
+                .map_err(|e| usage(&std::format!("invalid command: {}", e)))?;
+            if commands.is_empty() {
+                return Err(usage("send needs a command"));
+            }
+            Command::Send(commands)
+        }
+        "stream" => {
+            let mut number = |what: &str| {
+                args.next()
+                    .map(|v| {
+                        v.parse()
+                            .map_err(|_| usage(&std::format!("invalid {}", what)))
+                    })
+                    .transpose()
+            };
+            let samples = number("sample count")?;
+            let period_ms = number("period")?.unwrap_or(100);
+            Command::Stream { samples, period_ms }
+        }
+        other => return Err(usage(&std::format!("unknown command `{}`", other))),
+    };
+
+    if args.next().is_some() {
+        return Err(usage("too many arguments"));
+    }
+
+    Ok(Invocation {
+        bus,
+        address,
+        command,
+    })
+}
+
+/// Execute `command`, writing the results to `out`. `sleep_ms` is used between stream samples.
+pub fn run<I, E, W>(
+    iqs: &mut Iqs231<I>,
+    command: Command,
+    out: &mut W,
+    mut sleep_ms: impl FnMut(u32),
+) -> Result<(), CliError<E>>
+where
+    I: I2cInterface<Error = E>,
+    W: io::Write,
+{
+    match command {
+        Command::Probe => {
+            let prod_nr = iqs.get_prod_nr()?;
+            let version = iqs.get_software_version()?;
+            writeln!(out, "product number:   {:#04x}", prod_nr)?;
+            writeln!(out, "software version: {:?}", version)?;
+        }
+        Command::Dump => {
+            let snapshot = iqs.read_all()?;
+            writeln!(out, "main events: {:?}", snapshot.main_events)?;
+            writeln!(out, "{:#?}", snapshot.value)?;
+        }
+        Command::Get(register) => {
+            let value = iqs.read_register(register)?.value;
+            writeln!(
+                out,
+                "{:?} ({:#04x}) = {:#04x}",
+                register, register as u8, value
+            )?;
+        }
+        Command::Set(register, value) => {
+            iqs.write_register(register, value)?;
+            writeln!(
+                out,
+                "{:?} ({:#04x}) <- {:#04x}",
+                register, register as u8, value
+            )?;
+        }
+        Command::Send(commands) => {
+            iqs.send_commands(commands)?;
+            writeln!(out, "sent {:?}", commands)?;
+        }
+        Command::Stream { samples, period_ms } => {
+            writeln!(out, "{}", sampling::CSV_HEADER)?;
+            let mut n = 0;
+            let mut time_ms: u32 = 0;
+            while samples.is_none_or(|s| n < s) {
+                if n > 0 {
+                    sleep_ms(period_ms);
+                    // unbounded streams outlast the u32 timestamp, let it wrap
+                    time_ms = time_ms.wrapping_add(period_ms);
+                }
+                let sample = CountSample {
+                    time_ms,
+                    ..iqs.read_counts()?.value
+                };
+                sampling::write_csv_row(&sample, out)?;
+                out.flush()?;
+                n = n.saturating_add(1);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn usage(message: &str) -> UsageError {
+    UsageError(message.to_string())
+}
+
+fn parse_u8(value: &str) -> Option<u8> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+/// Register by (case insensitive) name as in `Register`, or by address
+fn parse_register(name: Option<&str>) -> Result<Register, UsageError> {
+    let name = name.ok_or_else(|| usage("missing register"))?;
+    let by_name = (0..REGISTER_COUNT as u8)
+        .filter_map(|r| Register::try_from(r).ok())
+        .find(|r| std::format!("{:?}", r).eq_ignore_ascii_case(name));
+
+    by_name
+        .or_else(|| parse_u8(name).and_then(|r| Register::try_from(r).ok()))
+        .ok_or_else(|| usage(&std::format!("unknown register `{}`", name)))
+}
+
+#[test]
+fn parses_invocations() {
+    let inv = parse_args(&["--address", "0x46", "set", "touchthreshold", "0x10"]).unwrap();
+    assert_eq!(inv.bus, DEFAULT_BUS);
+    assert_eq!(inv.address, I2cAddress::Alt1);
+    assert_eq!(inv.command, Command::Set(Register::TouchThreshold, 0x10));
+
+    let inv = parse_args(&["send", "TOGGLE_AC_FILTER", "|", "TOGGLE_ULP_MODE"]).unwrap();
+    assert_eq!(
+        inv.command,
+        Command::Send(Commands::TOGGLE_AC_FILTER | Commands::TOGGLE_ULP_MODE)
+    );
+
+    assert_eq!(
+        parse_args(&["get", "0x15"]).unwrap().command,
+        Command::Get(Register::CH0_ACF_H)
+    );
+    assert!(parse_args(&["get", "nope"]).is_err());
+    assert!(parse_args(&["--address", "0x40", "probe"]).is_err());
+    assert!(parse_args::<&str>(&[]).is_err());
+}
+
+#[test]
+fn runs_against_sim() {
+    use crate::sim::SimIqs231;
+
+    let sim = SimIqs231::new();
+    sim.set_count(Register::CH0_ACF_H, 990);
+    sim.set_count(Register::CH0_LTA_H, 1000);
+    let mut iqs = Iqs231::new(sim.clone());
+    let mut out = std::vec::Vec::new();
+
+    run(&mut iqs, Command::Probe, &mut out, |_| {}).unwrap();
+    run(
+        &mut iqs,
+        Command::Set(Register::CH0_Compensation, 42),
+        &mut out,
+        |_| {},
+    )
+    .unwrap();
+    let stream = Command::Stream {
+        samples: Some(2),
+        period_ms: 10,
+    };
+    let mut slept = 0;
+    run(&mut iqs, stream, &mut out, |ms| slept += ms).unwrap();
+
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("product number:   0x40"));
+    assert_eq!(sim.register(Register::CH0_Compensation), 42);
+    assert_eq!(out.matches(",990,1000,10,").count(), 2);
+    assert!(out.contains("\n10,990,1000,10,"));
+    assert_eq!(slept, 10);
+
+    let err = run(
+        &mut iqs,
+        Command::Set(Register::CH0_ACF_H, 1),
+        &mut std::io::sink(),
+        |_| {},
+    );
+    assert!(matches!(
+        err,
+        Err(CliError::Device(Error::RegisterNotWritable))
+    ));
+}
@@ -0,0 +1,223 @@
+use crate::{
+    device::{touch_threshold_from_reg, touch_threshold_to_reg, I2cAddress},
+    registers::{
+        ChannelMultiplier, Movement, OtpBank1, OtpBank2, OtpBank3, ProximityThreshold,
+        QuickRelease, Register,
+    },
+    snapshot::DeviceSnapshot,
+    Error,
+};
+
+/// Registers covered by `Config`, in the order they are written.
+/// These are all writable registers (see `Register::is_writable`) except `Reserved` and `Commands`,
+/// which hold no configuration.
+pub const CONFIG_REGISTERS: [Register; 12] = [
+    Register::OtpBank1,
+    Register::OtpBank2,
+    Register::OtpBank3,
+    Register::QuickRelease,
+    Register::Movement,
+    Register::TouchThreshold,
+    Register::ProximityThreshold,
+    Register::TempInterferenceThreshold,
+    Register::CH0_Multipliers,
+    Register::CH0_Compensation,
+    Register::CH1_Multipliers,
+    Register::CH1_Compensation,
+];
+
+/// Complete device configuration, see `Iqs231::apply_config()`, `read_config()` and `verify_config()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    pub otp_bank1: OtpBank1,
+    pub otp_bank2: OtpBank2,
+    pub otp_bank3: OtpBank3,
+    pub quick_release: QuickRelease,
+    pub movement: Movement,
+    /// Touch threshold in counts (4..=1024)
+    pub touch_threshold: u16,
+    pub proximity_threshold: ProximityThreshold,
+    pub temp_interference_threshold: u8,
+    pub ch0_multipliers: ChannelMultiplier,
+    pub ch0_compensation: u8,
+    pub ch1_multipliers: ChannelMultiplier,
+    pub ch1_compensation: u8,
+}
+
+impl Default for Config {
+    /// Power-on defaults according to the datasheet
+    fn default() -> Self {
+        Self {
+            otp_bank1: OtpBank1::default(),
+            otp_bank2: OtpBank2::default(),
+            otp_bank3: OtpBank3::default(),
+            quick_release: QuickRelease::default(),
+            movement: Movement::default(),
+            touch_threshold: touch_threshold_from_reg(0x07),
+            proximity_threshold: ProximityThreshold::Counts4,
+            temp_interference_threshold: 3,
+            ch0_multipliers: ChannelMultiplier::default(),
+            ch0_compensation: 0,
+            ch1_multipliers: ChannelMultiplier::default(),
+            ch1_compensation: 0,
+        }
+    }
+}
+
+impl Config {
+    /// Encode into register values, in the order of `CONFIG_REGISTERS`.
+    /// Fails if a value can not be represented in its register.
+    pub fn to_registers<E>(&self) -> Result<[u8; CONFIG_REGISTERS.len()], Error<E>> {
+        if self.movement.threshold() == 0 {
+            return Err(Error::MovementThresholdOutOfRange);
+        }
+
+        Ok([
+            self.otp_bank1.into_bytes()[0],
+            self.otp_bank2.into_bytes()[0],
+            self.otp_bank3.into_bytes()[0],
+            self.quick_release.into_bytes()[0],
+            self.movement.into_bytes()[0],
+            touch_threshold_to_reg(self.touch_threshold)?,
+            self.proximity_threshold.into(),
+            self.temp_interference_threshold,
+            self.ch0_multipliers.into_bytes()[0],
+            self.ch0_compensation,
+            self.ch1_multipliers.into_bytes()[0],
+            self.ch1_compensation,
+        ])
+    }
+
+    /// Decode from register values, in the order of `CONFIG_REGISTERS`
+    pub fn from_registers(regs: &[u8; CONFIG_REGISTERS.len()]) -> Self {
+        Self {
+            otp_bank1: OtpBank1::from_bytes([regs[0]]),
+            otp_bank2: OtpBank2::from_bytes([regs[1]]),
+            otp_bank3: OtpBank3::from_bytes([regs[2]]),
+            quick_release: QuickRelease::from_bytes([regs[3]]),
+            movement: Movement::from_bytes([regs[4]]),
+            touch_threshold: touch_threshold_from_reg(regs[5]),
+            proximity_threshold: regs[6].into(),
+            temp_interference_threshold: regs[7],
+            ch0_multipliers: ChannelMultiplier::from_bytes([regs[8]]),
+            ch0_compensation: regs[9],
+            ch1_multipliers: ChannelMultiplier::from_bytes([regs[10]]),
+            ch1_compensation: regs[11],
+        }
+    }
+
+    /// This configuration with `OtpBank1::i2c_addr` replaced by `address`
+    pub(crate) fn with_i2c_addr(mut self, address: I2cAddress) -> Self {
+        self.otp_bank1.set_i2c_addr(address);
+        self
+    }
+
+    /// Compare with the register values `actual` (in the order of `CONFIG_REGISTERS`, e.g. as read
+    /// back from the device), returning the differing registers (if any).
+    /// Fails as `to_registers()` if this configuration is invalid.
+    pub fn diff<E>(
+        &self,
+        actual: &[u8; CONFIG_REGISTERS.len()],
+    ) -> Result<Option<Mismatch>, Error<E>> {
+        Ok(Mismatch::between(&self.to_registers()?, actual))
+    }
+}
+
+impl From<&DeviceSnapshot> for Config {
+    fn from(snap: &DeviceSnapshot) -> Self {
+        Self {
+            otp_bank1: snap.otp_bank1,
+            otp_bank2: snap.otp_bank2,
+            otp_bank3: snap.otp_bank3,
+            quick_release: snap.quick_release,
+            movement: snap.movement,
+            touch_threshold: snap.touch_threshold,
+            proximity_threshold: snap.proximity_threshold,
+            temp_interference_threshold: snap.temp_interference_threshold,
+            ch0_multipliers: snap.ch0_multipliers,
+            ch0_compensation: snap.ch0_compensation,
+            ch1_multipliers: snap.ch1_multipliers,
+            ch1_compensation: snap.ch1_compensation,
+        }
+    }
+}
+
+/// Registers differing in a failed `Iqs231::verify_config()`, as a mask over `CONFIG_REGISTERS`
+/// (bit `i` is set when `CONFIG_REGISTERS[i]` differs)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Mismatch(u16);
+
+impl Mismatch {
+    /// Compare register values (in the order of `CONFIG_REGISTERS`), `None` if they are equal
+    pub fn between(
+        expected: &[u8; CONFIG_REGISTERS.len()],
+        actual: &[u8; CONFIG_REGISTERS.len()],
+    ) -> Option<Self> {
+        let mask = expected
+            .iter()
+            .zip(actual)
+            .enumerate()
+            .filter(|(_, (e, a))| e != a)
+            .fold(0, |mask, (i, _)| mask | 1 << i);
+
+        (mask != 0).then_some(Self(mask))
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    /// Registers whose read back value differs from the expected value
+    pub fn registers(&self) -> impl Iterator<Item = Register> {
+        let mask = self.0;
+        CONFIG_REGISTERS
+            .into_iter()
+            .enumerate()
+            .filter(move |(i, _)| mask & 1 << i != 0)
+            .map(|(_, reg)| reg)
+    }
+
+    pub fn contains(&self, register: Register) -> bool {
+        self.registers().any(|r| r == register)
+    }
+}
+
+#[test]
+fn config_register_roundtrip() {
+    let config = Config {
+        touch_threshold: 64,
+        proximity_threshold: ProximityThreshold::Counts10,
+        ch0_compensation: 120,
+        ..Default::default()
+    };
+
+    let regs = config.to_registers::<()>().unwrap();
+    assert_eq!(Config::from_registers(&regs), config);
+}
+
+#[test]
+fn config_diff_reports_differing_registers() {
+    let expected = Config::default();
+    let regs = expected.to_registers::<()>().unwrap();
+    assert_eq!(expected.diff::<()>(&regs).unwrap(), None);
+
+    let actual = Config {
+        ch1_compensation: 42,
+        quick_release: QuickRelease::new().with_beta(3),
+        ..expected
+    };
+
+    let mismatch = expected
+        .diff::<()>(&actual.to_registers::<()>().unwrap())
+        .unwrap()
+        .unwrap();
+    let mut regs = mismatch.registers();
+    assert_eq!(regs.next(), Some(Register::QuickRelease));
+    assert_eq!(regs.next(), Some(Register::CH1_Compensation));
+    assert_eq!(regs.next(), None);
+    assert!(!mismatch.contains(Register::OtpBank1));
+}
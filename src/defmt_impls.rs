@@ -0,0 +1,145 @@
+//! `defmt::Format` for the bitflags and bitfield types (`defmt` feature), which can not derive it.
+//! The other public types derive `defmt::Format` where they are defined.
+use defmt::{write, Format, Formatter};
+
+use crate::{
+    events::Events,
+    registers::{
+        AtiFlags, ChannelMultiplier, Commands, DebugEvents, EventFlags, MainEvents, Movement,
+        OtpBank1, OtpBank2, OtpBank3, QuickRelease, SystemFlags, UiFlags,
+    },
+    tuning::TuningWarnings,
+};
+
+/// `Name(FLAG_A | FLAG_B)`, with unknown bits appended in hex
+macro_rules! flags_format {
+    ($($name:ident),* $(,)?) => {$(
+        impl Format for $name {
+            fn format(&self, f: Formatter) {
+                write!(f, "{=str}(", stringify!($name));
+                let mut first = true;
+                for (name, _) in self.iter_names() {
+                    if !first {
+                        write!(f, " | ");
+                    }
+                    write!(f, "{=str}", name);
+                    first = false;
+                }
+                let unknown = self.bits() & !Self::all().bits();
+                if unknown != 0 {
+                    if !first {
+                        write!(f, " | ");
+                    }
+                    write!(f, "{=u8:#x}", unknown);
+                    first = false;
+                }
+                if first {
+                    write!(f, "empty");
+                }
+                write!(f, ")");
+            }
+        }
+    )*};
+}
+
+flags_format!(
+    Commands,
+    MainEvents,
+    DebugEvents,
+    SystemFlags,
+    UiFlags,
+    EventFlags,
+    AtiFlags,
+    TuningWarnings,
+);
+
+impl Format for OtpBank1 {
+    fn format(&self, f: Formatter) {
+        write!(
+            f,
+            "OtpBank1 {{ touch_thresh: {}, ac_filter: {}, prox_thresh: {}, i2c_addr: {} }}",
+            self.touch_thresh(),
+            self.ac_filter(),
+            self.prox_thresh(),
+            self.i2c_addr()
+        )
+    }
+}
+
+impl Format for OtpBank2 {
+    fn format(&self, f: Formatter) {
+        write!(
+            f,
+            "OtpBank2 {{ ui_select: {}, quick_release: {=bool}, failsafe_pulses_on_io1: {=bool}, \
+             base_value: {}, target: {}, increase_debounce: {=bool} }}",
+            self.ui_select(),
+            self.quick_release(),
+            self.failsafe_pulses_on_io1(),
+            self.base_value(),
+            self.target(),
+            self.increase_debounce()
+        )
+    }
+}
+
+impl Format for OtpBank3 {
+    fn format(&self, f: Formatter) {
+        write!(
+            f,
+            "OtpBank3 {{ sample_rate: {}, ati_events_on_io1: {=bool}, io2_function: {}, \
+             temp_n_interference_compensation: {=bool}, charge_transfer_freq: {} }}",
+            self.sample_rate(),
+            self.ati_events_on_io1(),
+            self.io2_function(),
+            self.temp_n_interference_compensation(),
+            self.charge_transfer_freq()
+        )
+    }
+}
+
+impl Format for QuickRelease {
+    fn format(&self, f: Formatter) {
+        write!(
+            f,
+            "QuickRelease {{ beta: {=u8}, threshold: {} }}",
+            self.beta(),
+            self.threshold()
+        )
+    }
+}
+
+impl Format for Movement {
+    fn format(&self, f: Formatter) {
+        write!(
+            f,
+            "Movement {{ threshold: {=u8}, debounce: {}, timeout: {} }}",
+            self.threshold(),
+            self.debounce(),
+            self.timeout()
+        )
+    }
+}
+
+impl Format for ChannelMultiplier {
+    fn format(&self, f: Formatter) {
+        write!(
+            f,
+            "ChannelMultiplier {{ compensation_multiplier: {=u8}, sensitivity_multiplier: {=u8} }}",
+            self.compensation_multiplier(),
+            self.sensitivity_multiplier()
+        )
+    }
+}
+
+impl Format for Events {
+    fn format(&self, f: Formatter) {
+        write!(f, "Events(");
+        for (i, event) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ");
+            }
+            write!(f, "{}", event);
+        }
+        write!(f, ")");
+    }
+}
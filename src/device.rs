@@ -1,17 +1,29 @@
-use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+use core::marker::PhantomData;
+
 use num_enum::TryFromPrimitive;
 
 use crate::{
+    config::{Config, Mismatch, CONFIG_REGISTERS},
+    events::{EventTracker, Events},
+    interface::{Delay, EventPin, I2cInterface},
+    logging::RegBytes,
+    metrics::{self, Metrics, NoiseEstimator, NoiseReport},
+    mode::{Mode, SensingDisabled, Standalone, Streaming, UltraLowPower},
     registers::{
-        self, ChannelMultiplier, Commands, DebugEvents, EventFlags, MainEvents, OtpBank1, OtpBank2,
-        OtpBank3, ProximityThreshold, QuickRelease, RegValue, Register, SoftwareVersion,
-        SystemFlags, UiFlags,
+        self, AcFilter, AtiFlags, ChannelMultiplier, Commands, DebugEvents, EventFlags, MainEvents,
+        Movement, OtpBank1, OtpBank2, OtpBank3, ProximityThreshold, QuickRelease, RegValue,
+        Register, SoftwareVersion, SystemFlags, UiFlags,
     },
-    Error,
+    sampling::{CountSample, SampleBuffer, SAMPLE_FIRST_REGISTER, SAMPLE_REGISTER_COUNT},
+    snapshot::DeviceSnapshot,
+    tuning::Recommendation,
+    Error, ModeChangeError,
 };
 
 #[repr(u8)]
 #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum I2cAddress {
     #[default]
     /// Default I2C address
@@ -24,9 +36,95 @@ pub enum I2cAddress {
     Alt2 = 0x47,
 }
 
-pub struct Iqs231<I> {
+impl I2cAddress {
+    pub const ALL: [I2cAddress; 4] = [Self::Default, Self::Test, Self::Alt1, Self::Alt2];
+}
+
+/// Probe all `I2cAddress`es on `bus`, returning the software version of the IQS231 found at each
+/// address (`None` when nothing, or another device, answers).
+pub fn discover<I: I2cInterface>(bus: &mut I) -> [(I2cAddress, Option<SoftwareVersion>); 4] {
+    I2cAddress::ALL.map(|address| {
+        // MainEvents, ProductNumber, SoftwareVersion
+        let mut buffer = [0u8; 3];
+        let version =
+            match bus.write_read(address as u8, &[Register::ProductNumber as u8], &mut buffer) {
+                Ok(()) if buffer[1] == registers::PRODUCT_NUMBER => {
+                    SoftwareVersion::try_from_primitive(buffer[2]).ok()
+                }
+                _ => None,
+            };
+        (address, version)
+    })
+}
+
+/// Touch threshold in counts (4..=1024) to register value
+pub(crate) fn touch_threshold_to_reg<E>(threshold: u16) -> Result<u8, Error<E>> {
+    if !(4..=1024).contains(&threshold) {
+        Err(Error::TouchThresholdOutOfRange)
+    } else {
+        Ok(((threshold - 4) >> 2) as u8)
+    }
+}
+
+/// Touch threshold register value to counts
+pub(crate) fn touch_threshold_from_reg(value: u8) -> u16 {
+    ((value as u16) << 2) + 4
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResetKind {
+    ColdBoot,
+    WarmBoot,
+}
+
+/// Device reset observed in managed mode, see `Iqs231::enable_managed_mode()`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ResetEvent {
+    pub kind: ResetKind,
+    /// The managed configuration was written back to the device, and the mode of the driver
+    /// entered again (see `mode`)
+    pub config_restored: bool,
+    /// `Commands::ATI_CH0` was issued after restoring the configuration
+    pub ati_triggered: bool,
+}
+
+struct Managed {
+    config: Config,
+    /// Address to move the device back to after a reset, see `Iqs231::change_address()`
+    address: I2cAddress,
+    redo_ati: bool,
+    /// A boot flag was present in the previous read, used to act on the first observation only
+    boot_seen: bool,
+    reset: Option<ResetEvent>,
+}
+
+/// Command toggled modes without a status flag, tracked by the driver and reset on boot
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+struct Modes {
+    ulp_mode: bool,
+    /// Set by `OtpBank1::ac_filter` (on boot or when written), `None` until read from the device
+    ac_filter: Option<bool>,
+}
+
+impl Modes {
+    fn seed_ac_filter(&mut self, otp: OtpBank1) {
+        self.ac_filter = Some(otp.ac_filter() != AcFilter::Off);
+    }
+}
+
+pub struct Iqs231<I, M = Streaming> {
     bus: I,
     address: I2cAddress,
+    /// Programmed address, the device answers here again after a reset
+    otp_address: I2cAddress,
+    managed: Option<Managed>,
+    events: EventTracker,
+    modes: Modes,
+    /// IO1 level seen by the previous `read_pending_events()`, to detect the falling edge
+    io1_low: bool,
+    mode: PhantomData<M>,
 }
 
 impl<I> Iqs231<I> {
@@ -34,36 +132,175 @@ impl<I> Iqs231<I> {
         Self {
             bus,
             address: I2cAddress::default(),
+            otp_address: I2cAddress::default(),
+            managed: None,
+            events: EventTracker::new(),
+            modes: Modes::default(),
+            io1_low: false,
+            mode: PhantomData,
         }
     }
 
+    /// Device programmed (OTP) to answer at `address`
     pub fn with_address(self, address: I2cAddress) -> Self {
-        Self { address, ..self }
+        Self {
+            address,
+            otp_address: address,
+            ..self
+        }
+    }
+}
+
+impl<I, M> Iqs231<I, M> {
+    pub fn address(&self) -> I2cAddress {
+        self.address
     }
 
     pub fn destroy(self) -> I {
         self.bus
     }
+
+    fn into_mode<N>(self) -> Iqs231<I, N> {
+        Iqs231 {
+            bus: self.bus,
+            address: self.address,
+            otp_address: self.otp_address,
+            managed: self.managed,
+            events: self.events,
+            modes: self.modes,
+            io1_low: self.io1_low,
+            mode: PhantomData,
+        }
+    }
 }
 
-impl<E, I> Iqs231<I>
+impl<E, I, M> Iqs231<I, M>
 where
-    I: Read<Error = E>,
+    I: I2cInterface<Error = E>,
+    M: Mode,
 {
     pub fn read_main_events(&mut self) -> Result<MainEvents, Error<E>> {
         let mut rd_buffer = [0u8; 1];
-        self.bus
-            .read(self.address as u8, &mut rd_buffer)
-            .map_err(|e| Error::IoError(e))?;
+        let result = self.bus.read(self.address as u8, &mut rd_buffer);
+        match result {
+            Err(_) if self.recover_address()? => self.bus.read(self.address as u8, &mut rd_buffer),
+            result => result,
+        }
+        .map_err(Error::IoError)?;
 
-        Ok(MainEvents::from_bits_retain(rd_buffer[0]))
+        let main_events = MainEvents::from_bits_retain(rd_buffer[0]);
+        self.check_reset(main_events)?;
+        Ok(main_events)
+    }
+
+    /// Block until IO1 signals a new event (falling edge, IO1 is active low), then read the
+    /// `MainEvents`. IO1 stays low for as long as the event lasts, so a call made while IO1 is
+    /// still low from the previous event waits for the next one.
+    /// This spins on the pin; to sleep in between events, wake up on the IO1 edge interrupt
+    /// and use `read_pending_events()`, or use the async driver.
+    pub fn wait_for_events<P: EventPin>(&mut self, io1: &mut P) -> Result<MainEvents, Error<E>> {
+        loop {
+            if let Some(events) = self.read_pending_events(io1)? {
+                return Ok(events);
+            }
+        }
+    }
+
+    /// Read the `MainEvents` only if IO1 went low since the previous call (a new event), without
+    /// touching the bus otherwise
+    pub fn read_pending_events<P: EventPin>(
+        &mut self,
+        io1: &mut P,
+    ) -> Result<Option<MainEvents>, Error<E>> {
+        let low = io1.is_low().map_err(|_| Error::PinError)?;
+        let falling_edge = low && !self.io1_low;
+        self.io1_low = low;
+
+        if falling_edge {
+            self.read_main_events().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Read `MainEvents`, `EventFlags` and `DebugEvents` and return the high level events
+    /// (proximity enter/exit, touch down/up, movement start/stop, quick release, ATI error and
+    /// reset) that occurred since the previous poll
+    pub fn poll(&mut self) -> Result<Events, Error<E>> {
+        let (main, flags) = self.get_event_flags()?.split();
+        let debug = self.get_debug_events()?;
+
+        Ok(self.events.update(main, flags, debug))
+    }
+
+    /// Number of consecutive polls a proximity, touch or movement level must be seen before
+    /// `poll()` reports it (default 1)
+    pub fn set_event_debounce(&mut self, samples: u8) {
+        self.events = self.events.with_debounce(samples);
+    }
+
+    /// Apply `config` and keep it: whenever a read observes `MainEvents::COLD_BOOT` or `WARM_BOOT`,
+    /// the device has reverted to its OTP defaults and the configuration is written again.
+    /// A device moved with `change_address()` answers at its programmed address after a reset:
+    /// when it stops responding, the driver looks for it there, restores the configuration and
+    /// moves it again.
+    /// With `redo_ati`, `Commands::ATI_CH0` is sent after restoring the configuration.
+    /// Observed resets are reported by `take_reset_event()`.
+    pub fn enable_managed_mode(&mut self, config: Config, redo_ati: bool) -> Result<(), Error<E>> {
+        self.apply_config(&config)?;
+        self.managed = Some(Managed {
+            config,
+            address: self.address,
+            redo_ati,
+            boot_seen: false,
+            reset: None,
+        });
+        Ok(())
+    }
+
+    /// Stop restoring the configuration on reset, returns the managed configuration
+    pub fn disable_managed_mode(&mut self) -> Option<Config> {
+        self.managed.take().map(|m| m.config)
+    }
+
+    /// Return (and clear) the last reset observed in managed mode
+    pub fn take_reset_event(&mut self) -> Option<ResetEvent> {
+        self.managed.as_mut().and_then(|m| m.reset.take())
+    }
+
+    /// Move the device to `address` by writing `OtpBank1::i2c_addr`, and continue talking to it
+    /// there. The change is confirmed by reading the product number at the new address; on failure
+    /// the driver keeps using the old address.
+    ///
+    /// The address lives in RAM: after a reset the device answers at its programmed (OTP) address
+    /// again (in managed mode the driver follows it and moves it back, see
+    /// `enable_managed_mode()`). `I2cAddress::Test` is refused.
+    pub fn change_address(&mut self, address: I2cAddress) -> Result<(), Error<E>> {
+        if address == I2cAddress::Test {
+            return Err(Error::AddressNotAllowed);
+        }
+
+        let otp = self.get_otp_bank1()?.value.with_i2c_addr(address);
+        self.set_otp_bank1(otp)?;
+
+        let old = core::mem::replace(&mut self.address, address);
+        if let Err(e) = self.get_prod_nr() {
+            self.address = old;
+            warn!(
+                "Device not found at {:?} after address change, keeping {:?}",
+                address, old
+            );
+            return Err(e);
+        }
+
+        info!("I2C address changed from {:?} to {:?}", old, address);
+
+        if let Some(managed) = self.managed.as_mut() {
+            managed.address = address;
+        }
+        Ok(())
     }
-}
 
-impl<E, I> Iqs231<I>
-where
-    I: Write<Error = E> + WriteRead<Error = E>,
-{
     pub fn get_prod_nr(&mut self) -> Result<u8, Error<E>> {
         let prod_nr = self.read_reg(Register::ProductNumber)?.value;
         if prod_nr == registers::PRODUCT_NUMBER {
@@ -74,7 +311,7 @@ where
     }
 
     pub fn get_software_version(&mut self) -> Result<SoftwareVersion, Error<E>> {
-        let ver = self.read_reg(Register::ProductNumber)?.value;
+        let ver = self.read_reg(Register::SoftwareVersion)?.value;
         SoftwareVersion::try_from_primitive(ver).map_err(|_| Error::UnknownSoftwareVersion(ver))
     }
 
@@ -111,19 +348,28 @@ where
         Ok(regval)
     }
 
-    pub fn set_touch_threshold(&mut self, threshold: u16) -> Result<(), Error<E>> {
-        if threshold < 4 || threshold > 1024 {
-            Err(Error::TouchThresholdOutOfRange)
+    pub fn set_movement(&mut self, movement: Movement) -> Result<(), Error<E>> {
+        if movement.threshold() == 0 {
+            Err(Error::MovementThresholdOutOfRange)
         } else {
-            let value = (threshold - 4) >> 2;
-            self.write_reg(Register::TouchThreshold, value as u8)
+            self.write_reg(Register::Movement, movement.into_bytes()[0])
         }
     }
 
+    pub fn get_movement(&mut self) -> Result<RegValue<Movement>, Error<E>> {
+        let rv = self.read_reg(Register::Movement)?;
+        Ok(rv.map(|v| Movement::from_bytes([v])))
+    }
+
+    pub fn set_touch_threshold(&mut self, threshold: u16) -> Result<(), Error<E>> {
+        let value = touch_threshold_to_reg(threshold)?;
+        self.write_reg(Register::TouchThreshold, value)
+    }
+
     pub fn get_touch_threshold(&mut self) -> Result<RegValue<u16>, Error<E>> {
         Ok(self
             .read_reg(Register::TouchThreshold)?
-            .map(|v| ((v as u16) << 2) + 4))
+            .map(touch_threshold_from_reg))
     }
 
     pub fn set_proximity_threshold(
@@ -143,6 +389,10 @@ where
         self.write_reg(Register::TempInterferenceThreshold, threshold)
     }
 
+    pub fn get_temp_interference_threshold(&mut self) -> Result<RegValue<u8>, Error<E>> {
+        self.read_reg(Register::TempInterferenceThreshold)
+    }
+
     pub fn set_ch0_multipliers(&mut self, mult: ChannelMultiplier) -> Result<(), Error<E>> {
         self.write_reg(Register::CH0_Multipliers, mult.into_bytes()[0])
     }
@@ -184,7 +434,7 @@ where
     }
 
     pub fn get_debug_events(&mut self) -> Result<DebugEvents, Error<E>> {
-        let value = self.read_reg(Register::ProductNumber)?.value;
+        let value = self.read_reg(Register::DebugEvents)?.value;
         Ok(DebugEvents::from_bits_retain(value))
     }
 
@@ -198,6 +448,13 @@ where
         Ok(UiFlags::from_bits_retain(value))
     }
 
+    pub fn get_ati_flags(&mut self) -> Result<RegValue<AtiFlags>, Error<E>> {
+        let reg = self
+            .read_reg(Register::ATI_Flags)?
+            .map(AtiFlags::from_bits_retain);
+        Ok(reg)
+    }
+
     pub fn get_event_flags(&mut self) -> Result<RegValue<EventFlags>, Error<E>> {
         let reg = self
             .read_reg(Register::EventFlags)?
@@ -214,6 +471,407 @@ where
         Ok(rv.map(|v| QuickRelease::from_bytes([v])))
     }
 
+    /// Countdown timer to give active feedback on the time-out. Movement events will reset this timer
+    /// (0 – 255) × 100ms | Timer range: 0 – 90min
+    pub fn get_lta_halt_timer(&mut self) -> Result<RegValue<u16>, Error<E>> {
+        self.read_reg16(Register::LtaHaltTimer_H)
+    }
+
+    // FILTER_HALT_TIMER R n/a Countdown timer to give active feedback on the fixed 5sec time-out when in filter halt mode (before entering Proximity detect)
+    // 0 – 50 x 100ms | Timer range: 0 – 5 seconds
+    pub fn get_filter_halt_timer(&mut self) -> Result<RegValue<u8>, Error<E>> {
+        self.read_reg(Register::FilterHaltTimer)
+    }
+
+    // TIMER_READ_INPUT R n/a Countdown timer to signal when a read operation is done on IO2
+    // (0 – 10) x 100ms | Timer range: 0 – 1 seconds
+    pub fn get_timer_read_input(&mut self) -> Result<RegValue<u8>, Error<E>> {
+        self.read_reg(Register::TimerReadInput)
+    }
+
+    // TIMER_REDO_ATI R n/a
+    // Countdown timer to give active feedback on the time until re-calibration is attempted after ATI-error
+    // (0 – 255) × 100ms | Timer range: 0 – 25s
+    pub fn get_timer_redo_ati(&mut self) -> Result<RegValue<u8>, Error<E>> {
+        self.read_reg(Register::TimerRedoAti)
+    }
+
+    /// Read the complete register map (`ProductNumber` up to `TimerRedoAti`) in a single burst
+    pub fn read_all(&mut self) -> Result<RegValue<DeviceSnapshot>, Error<E>> {
+        let regs = self.read_burst::<{ registers::REGISTER_COUNT }>(Register::ProductNumber)?;
+        Ok(regs.map(|r| DeviceSnapshot::from_registers(&r)))
+    }
+
+    /// Write all configuration registers (see `config::CONFIG_REGISTERS`).
+    /// The configuration is validated before anything is written.
+    ///
+    /// `OtpBank1::i2c_addr` is kept at the address the driver talks to, use `change_address()`
+    /// to move the device.
+    pub fn apply_config(&mut self, config: &Config) -> Result<(), Error<E>> {
+        let values = config.with_i2c_addr(self.address).to_registers()?;
+        for (reg, value) in CONFIG_REGISTERS.into_iter().zip(values) {
+            self.write_reg(reg, value)?;
+        }
+        Ok(())
+    }
+
+    /// Read all configuration registers in a single burst
+    pub fn read_config(&mut self) -> Result<RegValue<Config>, Error<E>> {
+        let regs = self.read_burst::<{ CONFIG_REGISTERS.len() }>(CONFIG_REGISTERS[0])?;
+        Ok(regs.map(|r| Config::from_registers(&r)))
+    }
+
+    /// Read back the configuration and compare it with `config`.
+    /// Returns `Error::ConfigMismatch` listing the differing registers if they do not match, the
+    /// range errors of `Config::to_registers()` if `config` is invalid.
+    /// `OtpBank1::i2c_addr` is not compared, see `apply_config()`.
+    pub fn verify_config(&mut self, config: &Config) -> Result<(), Error<E>> {
+        // an invalid configuration is reported as such, not as a mismatch
+        let expected = config.with_i2c_addr(self.address).to_registers()?;
+        let actual = self
+            .read_burst::<{ CONFIG_REGISTERS.len() }>(CONFIG_REGISTERS[0])?
+            .value;
+        match Mismatch::between(&expected, &actual) {
+            Some(mismatch) => Err(Error::ConfigMismatch(mismatch)),
+            None => Ok(()),
+        }
+    }
+
+    /// Write the settings of a tuning recommendation (see `tuning::recommend()`)
+    pub fn apply_tuning(&mut self, recommendation: &Recommendation) -> Result<(), Error<E>> {
+        self.set_proximity_threshold(recommendation.proximity_threshold)?;
+        self.set_touch_threshold(recommendation.touch_threshold)?;
+        self.set_quick_release(recommendation.quick_release)?;
+        self.set_ch0_multipliers(recommendation.ch0_multipliers)
+    }
+
+    /// Use this function (taking ownership of device) to put device in standalone mode.
+    /// The device no longer answers on I²C, the returned driver only renders the bus
+    /// (`destroy()`).
+    pub fn into_standalone(mut self) -> Result<Iqs231<I, Standalone>, ModeChangeError<E, Self>> {
+        match self.write_reg(Register::Commands, Commands::STANDALONE.bits()) {
+            Ok(()) => Ok(self.into_mode()),
+            Err(error) => Err(ModeChangeError { error, dev: self }),
+        }
+    }
+
+    /// Send command(s)
+    /// Sending command "STANDALONE" ("WARM_BOOT") NOT allowed, as this disables i2c on the device.
+    /// use `into_standalone()` to issue this the `STANDALONE` command, set the device in standalone modde and render the I²C bus
+    ///
+    /// Prefer the mode transitions (see `mode`), `set_ac_filter()` and `trigger_ati()` over raw
+    /// toggles, which change the device mode without changing the type of the driver.
+    pub fn send_commands(&mut self, commands: Commands) -> Result<(), Error<E>> {
+        if commands.contains(Commands::STANDALONE) {
+            warn!("{:?} refused, use `into_standalone()`", commands);
+            return Err(Error::ShutdownCommandNotAllowed);
+        }
+
+        debug!("Send {:?}", commands);
+
+        if commands.contains(Commands::TOGGLE_AC_FILTER) {
+            // the state to toggle from
+            self.ac_filter()?;
+        }
+        self.write_reg(Register::Commands, commands.bits())?;
+        if commands.contains(Commands::TOGGLE_ULP_MODE) {
+            self.modes.ulp_mode = !self.modes.ulp_mode;
+        }
+        if commands.contains(Commands::TOGGLE_AC_FILTER) {
+            self.modes.ac_filter = self.modes.ac_filter.map(|on| !on);
+        }
+        Ok(())
+    }
+
+    /// ULP mode as tracked by the driver (there is no status flag), off after a reset
+    pub fn ulp_mode(&self) -> bool {
+        self.modes.ulp_mode
+    }
+
+    /// AC filter state as tracked by the driver (there is no status flag). After a reset it
+    /// follows `OtpBank1::ac_filter`, which is read from the device when not known yet.
+    pub fn ac_filter(&mut self) -> Result<bool, Error<E>> {
+        if let Some(on) = self.modes.ac_filter {
+            return Ok(on);
+        }
+        let otp = self.get_otp_bank1()?.value;
+        self.modes.seed_ac_filter(otp);
+        Ok(otp.ac_filter() != AcFilter::Off)
+    }
+
+    /// Switch the AC filter on or off, toggling only when needed. As there is no status flag, the
+    /// change can not be confirmed; a later reset reverts it (and `ac_filter()` with it).
+    pub fn set_ac_filter(&mut self, enable: bool) -> Result<(), Error<E>> {
+        self.set_tracked_mode(Commands::TOGGLE_AC_FILTER, Self::ac_filter, enable)
+    }
+
+    pub fn is_sensing_enabled(&mut self) -> Result<bool, Error<E>> {
+        Ok(!self.get_ui_flags()?.contains(UiFlags::UI_SENSING_DISABLD))
+    }
+
+    /// Raw read of a single register
+    pub fn read_register(&mut self, register: Register) -> Result<RegValue<u8>, Error<E>> {
+        self.read_reg(register)
+    }
+
+    /// Raw write of a single writable register. Prefer the typed setters, this bypasses their
+    /// range checks (`Commands` are still checked as in `send_commands()`).
+    pub fn write_register(&mut self, register: Register, value: u8) -> Result<(), Error<E>> {
+        match register {
+            Register::Commands => self.send_commands(Commands::from_bits_retain(value)),
+            _ => self.write_reg(register, value),
+        }
+    }
+
+    fn read_reg16(&mut self, register: impl Into<Register>) -> Result<RegValue<u16>, Error<E>> {
+        Ok(self.read_burst::<2>(register)?.map(u16::from_be_bytes))
+    }
+
+    /// Read register converted into the specified type (using `From<u8>`)
+    fn read_reg_t<T: From<u8>>(
+        &mut self,
+        register: impl Into<Register>,
+    ) -> Result<RegValue<T>, Error<E>> {
+        self.read_reg(register).map(|rv| rv.map(T::from))
+    }
+
+    fn read_reg(&mut self, register: impl Into<Register>) -> Result<RegValue<u8>, Error<E>> {
+        Ok(self.read_burst::<1>(register)?.map(|[v]| v))
+    }
+
+    /// Read `N` consecutive registers starting at `register` in a single I²C transaction.
+    /// The device auto-increments the register address after the leading `MainEvents` byte,
+    /// so multi-byte values can not tear between reads.
+    pub(crate) fn read_burst<const N: usize>(
+        &mut self,
+        register: impl Into<Register>,
+    ) -> Result<RegValue<[u8; N]>, Error<E>> {
+        const { assert!(N > 0 && N <= registers::REGISTER_COUNT) };
+
+        let reg: Register = register.into();
+        Register::from_u8(reg as u8 + (N as u8 - 1))?;
+
+        let mut rd_buffer = [0u8; registers::REGISTER_COUNT + 1];
+        let rd_buffer = &mut rd_buffer[..=N];
+
+        let result = self
+            .bus
+            .write_read(self.address as u8, &[reg as u8], rd_buffer);
+        match result {
+            Err(_) if self.recover_address()? => {
+                self.bus
+                    .write_read(self.address as u8, &[reg as u8], rd_buffer)
+            }
+            result => result,
+        }
+        .map_err(|e| {
+            warn!("Read {:?} at {:?} failed", reg, self.address);
+            Error::IoError(e)
+        })?;
+
+        let regval = RegValue::from_burst(rd_buffer);
+        trace!(
+            "Read {:?} -> {:?} ({:?})",
+            reg,
+            RegBytes {
+                register: reg,
+                bytes: &regval.value,
+            },
+            regval.main_events
+        );
+        self.check_reset(regval.main_events)?;
+        Ok(regval)
+    }
+
+    /// Toggle the tracked mode read by `mode` when it differs from `enable`
+    fn set_tracked_mode(
+        &mut self,
+        toggle: Commands,
+        mode: fn(&mut Self) -> Result<bool, Error<E>>,
+        enable: bool,
+    ) -> Result<(), Error<E>> {
+        // pick up a pending boot flag first, a reset reverts the modes
+        self.read_main_events()?;
+        if mode(self)? == enable {
+            return Ok(());
+        }
+
+        self.send_commands(toggle)?;
+        info!("Mode {:?} -> {}", toggle, enable);
+        Ok(())
+    }
+
+    /// Switch ULP mode on or off, toggling only when needed (not confirmed, see `set_ac_filter()`)
+    fn set_ulp_mode(&mut self, enable: bool) -> Result<(), Error<E>> {
+        self.set_tracked_mode(Commands::TOGGLE_ULP_MODE, |d| Ok(d.ulp_mode()), enable)
+    }
+
+    fn is_ati_busy(&mut self) -> Result<bool, Error<E>> {
+        let [system, _ui, ati] = self.read_burst::<3>(Register::System_Flags)?.value;
+        Ok(
+            SystemFlags::from_bits_retain(system).contains(SystemFlags::ATI_MODE)
+                || AtiFlags::from_bits_retain(ati).contains(AtiFlags::CH0_ATI_BUSY),
+        )
+    }
+
+    /// Enable or disable sensing when needed, confirmed by `UiFlags::UI_SENSING_DISABLD`
+    fn set_sensing(&mut self, enable: bool) -> Result<(), Error<E>> {
+        if self.is_sensing_enabled()? == enable {
+            return Ok(());
+        }
+        self.send_commands(if enable {
+            Commands::ENABLE_SENSING
+        } else {
+            Commands::DISABLE_SENSING
+        })?;
+        if self.is_sensing_enabled()? == enable {
+            info!("Sensing enabled -> {}", enable);
+            Ok(())
+        } else {
+            warn!("Sensing enabled -> {} not confirmed", enable);
+            Err(Error::ModeChangeNotConfirmed)
+        }
+    }
+
+    /// Reset the tracked modes on boot, and in managed mode restore the configuration when a
+    /// (new) boot flag shows up
+    fn check_reset(&mut self, main_events: MainEvents) -> Result<(), Error<E>> {
+        if main_events.intersects(MainEvents::COLD_BOOT | MainEvents::WARM_BOOT)
+            && self.modes != Modes::default()
+        {
+            info!(
+                "Boot flag in {:?}, tracked modes reverted to defaults",
+                main_events
+            );
+            self.modes = Modes::default();
+        }
+
+        let Some(managed) = self.managed.as_mut() else {
+            return Ok(());
+        };
+
+        let kind = if main_events.contains(MainEvents::COLD_BOOT) {
+            Some(ResetKind::ColdBoot)
+        } else if main_events.contains(MainEvents::WARM_BOOT) {
+            Some(ResetKind::WarmBoot)
+        } else {
+            None
+        };
+
+        let first_seen = !managed.boot_seen;
+        managed.boot_seen = kind.is_some();
+
+        let Some(kind) = kind.filter(|_| first_seen) else {
+            return Ok(());
+        };
+        let config = managed.config;
+        let address = managed.address;
+        let redo_ati = managed.redo_ati;
+
+        info!("Reset detected ({:?}), restoring configuration", kind);
+
+        let restored = self
+            .apply_config(&config)
+            .and_then(|()| {
+                if self.address != address {
+                    self.change_address(address)
+                } else {
+                    Ok(())
+                }
+            })
+            // the device is back to streaming, enter the mode of the driver again
+            .and_then(|()| {
+                if M::COMMANDS.is_empty() {
+                    Ok(())
+                } else {
+                    self.send_commands(M::COMMANDS)
+                }
+            });
+        let ati = match (&restored, redo_ati) {
+            (Ok(()), true) => Some(self.send_commands(Commands::ATI_CH0)),
+            _ => None,
+        };
+
+        let reset = ResetEvent {
+            kind,
+            config_restored: restored.is_ok(),
+            ati_triggered: matches!(ati, Some(Ok(()))),
+        };
+        debug!("{:?}", reset);
+        if let Some(managed) = self.managed.as_mut() {
+            managed.reset = Some(reset);
+        }
+
+        restored?;
+        ati.unwrap_or(Ok(()))
+    }
+
+    /// After a failed transaction in managed mode, look for a device moved by `change_address()`
+    /// at its programmed address. When it answers there it has been reset: talk to it there and
+    /// let `check_reset()` restore the configuration and the address. Returns whether to retry.
+    fn recover_address(&mut self) -> Result<bool, Error<E>> {
+        if self.managed.is_none() || self.address == self.otp_address {
+            return Ok(false);
+        }
+
+        let mut rd_buffer = [0u8; 1];
+        if self
+            .bus
+            .read(self.otp_address as u8, &mut rd_buffer)
+            .is_err()
+        {
+            return Ok(false);
+        }
+
+        warn!(
+            "No response at {:?}, device found at its programmed address {:?}",
+            self.address, self.otp_address
+        );
+        self.address = self.otp_address;
+        self.check_reset(MainEvents::from_bits_retain(rd_buffer[0]))?;
+        Ok(true)
+    }
+
+    fn write_reg(&mut self, register: impl Into<Register>, value: u8) -> Result<(), Error<E>> {
+        let reg: Register = register.into();
+
+        trace!(
+            "Write {:?} <- {:?}",
+            reg,
+            RegBytes {
+                register: reg,
+                bytes: &[value],
+            }
+        );
+
+        if reg.is_writable() {
+            let result = self.bus.write(self.address as u8, &[reg as u8, value]);
+            match result {
+                Err(_) if self.recover_address()? => {
+                    self.bus.write(self.address as u8, &[reg as u8, value])
+                }
+                result => result,
+            }
+            .map_err(|e| {
+                warn!("Write {:?} at {:?} failed", reg, self.address);
+                Error::IoError(e)
+            })?;
+
+            if reg == Register::OtpBank1 {
+                self.modes.seed_ac_filter(OtpBank1::from_bytes([value]));
+            }
+            Ok(())
+        } else {
+            warn!("Register {:?} is not writable", reg);
+            Err(Error::RegisterNotWritable)
+        }
+    }
+}
+
+impl<E, I> Iqs231<I, Streaming>
+where
+    I: I2cInterface<Error = E>,
+{
     /// Proximity channel: Filtered count value
     /// (0-2000)
     pub fn get_prox_filtered_count(&mut self) -> Result<RegValue<u16>, Error<E>> {
@@ -247,7 +905,7 @@ where
     /// Movement channel: Lower reference count value
     /// (0-2000)
     pub fn get_move_lower_reference_count(&mut self) -> Result<RegValue<u16>, Error<E>> {
-        self.read_reg16(Register::CH1_LMOV_L)
+        self.read_reg16(Register::CH1_LMOV_H)
     }
 
     /// Temperature channel: Unfiltered count value (if temperature feature enabled)
@@ -261,98 +919,414 @@ where
     pub fn get_temp_reference(&mut self) -> Result<RegValue<u16>, Error<E>> {
         self.read_reg16(Register::Temperature_H)
     }
-    /// Countdown timer to give active feedback on the time-out. Movement events will reset this timer
-    /// (0 – 255) × 100ms | Timer range: 0 – 90min
-    pub fn get_lta_halt_timer(&mut self) -> Result<RegValue<u16>, Error<E>> {
-        self.read_reg16(Register::LtaHaltTimer_H)
+
+    /// Read the channel counts, event flags and thresholds in a single burst
+    pub fn read_counts(&mut self) -> Result<RegValue<CountSample>, Error<E>> {
+        let regs = self.read_burst::<SAMPLE_REGISTER_COUNT>(SAMPLE_FIRST_REGISTER)?;
+        Ok(regs.map(|r| CountSample::from_registers(&r)))
     }
 
-    // FILTER_HALT_TIMER R n/a Countdown timer to give active feedback on the fixed 5sec time-out when in filter halt mode (before entering Proximity detect)
-    // 0 – 50 x 100ms | Timer range: 0 – 5 seconds
-    pub fn get_filter_halt_timer(&mut self) -> Result<RegValue<u8>, Error<E>> {
-        self.read_reg(Register::FilterHaltTimer)
+    /// Take `count` samples, `period_ms` apart, into `buffer`.
+    /// Sample times continue from the most recent sample already in the buffer.
+    pub fn sample_counts<D: Delay, const N: usize>(
+        &mut self,
+        buffer: &mut SampleBuffer<N>,
+        delay: &mut D,
+        period_ms: u32,
+        count: usize,
+    ) -> Result<(), Error<E>> {
+        for _ in 0..count {
+            let time_ms = match buffer.latest() {
+                Some(latest) => {
+                    delay.delay_ms(period_ms);
+                    latest.time_ms.wrapping_add(period_ms)
+                }
+                None => 0,
+            };
+            let sample = CountSample {
+                time_ms,
+                ..self.read_counts()?.value
+            };
+            buffer.push(sample);
+        }
+        Ok(())
     }
 
-    // TIMER_READ_INPUT R n/a Countdown timer to signal when a read operation is done on IO2
-    // (0 – 10) x 100ms | Timer range: 0 – 1 seconds
-    pub fn get_timer_read_input(&mut self) -> Result<RegValue<u8>, Error<E>> {
-        self.read_reg(Register::TimerReadInput)
+    /// Proximity delta (`CH0_LTA − CH0_ACF`), positive when an object approaches
+    pub fn get_proximity_delta(&mut self) -> Result<RegValue<i32>, Error<E>> {
+        let regs = self.read_burst::<4>(Register::CH0_ACF_H)?;
+        Ok(regs.map(|[acf_h, acf_l, lta_h, lta_l]| {
+            metrics::proximity_delta(
+                u16::from_be_bytes([lta_h, lta_l]),
+                u16::from_be_bytes([acf_h, acf_l]),
+            )
+        }))
     }
 
-    // TIMER_REDO_ATI R n/a
-    // Countdown timer to give active feedback on the time until re-calibration is attempted after ATI-error
-    // (0 – 255) × 100ms | Timer range: 0 – 25s
-    pub fn get_timer_redo_ati(&mut self) -> Result<RegValue<u8>, Error<E>> {
-        self.read_reg(Register::TimerRedoAti)
+    /// Movement window width (`CH1_UMOV − CH1_LMOV`)
+    pub fn get_movement_window(&mut self) -> Result<RegValue<i32>, Error<E>> {
+        let regs = self.read_burst::<4>(Register::CH1_UMOV_H)?;
+        Ok(regs.map(|[umov_h, umov_l, lmov_h, lmov_l]| {
+            metrics::movement_window(
+                u16::from_be_bytes([umov_h, umov_l]),
+                u16::from_be_bytes([lmov_h, lmov_l]),
+            )
+        }))
     }
 
-    /// Use this function (taking ownership of device) to put device in standalone mode
-    /// returns the the I²C bus
-    pub fn into_standalone(mut self) -> Result<I, Error<E>> {
-        self.write_reg(Register::Commands, Commands::STANDALONE.bits())?;
-        Ok(self.destroy())
+    /// Proximity delta, distance to the thresholds and movement window from a single burst
+    pub fn read_metrics(&mut self) -> Result<RegValue<Metrics>, Error<E>> {
+        Ok(self.read_counts()?.map(|s| Metrics::from(&s)))
     }
 
-    /// Send command(s)
-    /// Sending command "STANDALONE" ("WARM_BOOT") NOT allowed, as this disables i2c on the device.
-    /// use `into_standalone()` to issue this the `STANDALONE` command, set the device in standalone modde and render the I²C bus
-    pub fn send_commands(&mut self, commands: Commands) -> Result<(), Error<E>> {
-        if commands.contains(Commands::STANDALONE) {
-            Err(Error::ShutdownCommandNotAllowed)
+    /// Estimate the noise on the proximity channel from `count` readings, `period_ms` apart
+    pub fn estimate_noise<D: Delay>(
+        &mut self,
+        delay: &mut D,
+        period_ms: u32,
+        count: usize,
+    ) -> Result<NoiseReport, Error<E>> {
+        let mut filtered = NoiseEstimator::new();
+        let mut delta = NoiseEstimator::new();
+        for i in 0..count {
+            if i > 0 {
+                delay.delay_ms(period_ms);
+            }
+            let sample = self.read_counts()?.value;
+            filtered.push(sample.prox_filtered as i32);
+            delta.push(sample.delta());
+        }
+        Ok(NoiseReport {
+            filtered: filtered.stats(),
+            delta: delta.stats(),
+        })
+    }
+
+    /// Start ATI on CH0 without waiting for it to complete (see `run_ati()` for that).
+    /// Confirmed by `SystemFlags::ATI_MODE` or `AtiFlags::CH0_ATI_BUSY`; when these are already
+    /// set, the running ATI is left alone and no command is sent.
+    pub fn trigger_ati(&mut self) -> Result<(), Error<E>> {
+        if self.is_ati_busy()? {
+            debug!("ATI already running");
+            return Ok(());
+        }
+        self.send_commands(Commands::ATI_CH0)?;
+        if self.is_ati_busy()? {
+            Ok(())
         } else {
-            self.write_reg(Register::Commands, commands.bits())
+            warn!("ATI start not confirmed");
+            Err(Error::ModeChangeNotConfirmed)
         }
     }
 
-    fn read_reg16(&mut self, register: impl Into<Register>) -> Result<RegValue<u16>, Error<E>> {
-        let reg: Register = register.into();
-        let hi = self.read_reg(reg)?;
-        let lo = self.read_reg(reg.next()?)?;
+    /// Disable sensing, confirmed by `UiFlags::UI_SENSING_DISABLD`
+    pub fn into_sensing_disabled(
+        mut self,
+    ) -> Result<Iqs231<I, SensingDisabled>, ModeChangeError<E, Self>> {
+        match self.set_sensing(false) {
+            Ok(()) => Ok(self.into_mode()),
+            Err(error) => Err(ModeChangeError { error, dev: self }),
+        }
+    }
 
-        Ok(RegValue {
-            main_events: hi.main_events | lo.main_events,
-            value: (hi.value as u16) << 8 | lo.value as u16,
-        })
+    /// Switch to ULP mode. As there is no status flag, the change can not be confirmed.
+    pub fn into_ulp(mut self) -> Result<Iqs231<I, UltraLowPower>, ModeChangeError<E, Self>> {
+        match self.set_ulp_mode(true) {
+            Ok(()) => Ok(self.into_mode()),
+            Err(error) => Err(ModeChangeError { error, dev: self }),
+        }
     }
+}
 
-    /// Read register converted into the specified type (using `From<u8>`)
-    fn read_reg_t<T: From<u8>>(
-        &mut self,
-        register: impl Into<Register>,
-    ) -> Result<RegValue<T>, Error<E>> {
-        self.read_reg(register).map(|rv| rv.map(T::from))
+impl<E, I> Iqs231<I, SensingDisabled>
+where
+    I: I2cInterface<Error = E>,
+{
+    /// Enable sensing again, confirmed by `UiFlags::UI_SENSING_DISABLD`
+    pub fn into_streaming(mut self) -> Result<Iqs231<I, Streaming>, ModeChangeError<E, Self>> {
+        match self.set_sensing(true) {
+            Ok(()) => Ok(self.into_mode()),
+            Err(error) => Err(ModeChangeError { error, dev: self }),
+        }
     }
+}
 
-    fn read_reg(&mut self, register: impl Into<Register>) -> Result<RegValue<u8>, Error<E>> {
-        let reg: Register = register.into();
-        let mut rd_buffer = [0u8; 2];
+impl<E, I> Iqs231<I, UltraLowPower>
+where
+    I: I2cInterface<Error = E>,
+{
+    /// Leave ULP mode (unless a reset already did), not confirmed as `into_ulp()`
+    pub fn into_streaming(mut self) -> Result<Iqs231<I, Streaming>, ModeChangeError<E, Self>> {
+        match self.set_ulp_mode(false) {
+            Ok(()) => Ok(self.into_mode()),
+            Err(error) => Err(ModeChangeError { error, dev: self }),
+        }
+    }
+}
 
-        self.bus
-            .write_read(self.address as u8, &[reg as u8], &mut rd_buffer)
-            .map_err(|e| Error::IoError(e))?;
-
-        #[cfg(feature = "defmt")]
-        defmt::trace!(
-            "Read reg [{}] -> {:#x}",
-            defmt::Debug2Format(&reg),
-            rd_buffer
-        );
+/// IO1 event pin driven by the test
+#[cfg(test)]
+struct TestPin {
+    low: bool,
+}
+
+#[cfg(test)]
+impl EventPin for TestPin {
+    type Error = ();
 
-        Ok(rd_buffer.into())
+    fn is_low(&mut self) -> Result<bool, ()> {
+        Ok(self.low)
     }
+}
 
-    fn write_reg(&mut self, register: impl Into<Register>, value: u8) -> Result<(), Error<E>> {
-        let reg: Register = register.into();
+#[test]
+fn io1_events_are_read_on_the_falling_edge() {
+    let (sim, mut iqs) = crate::sim::test_device(None);
+    let mut io1 = TestPin { low: true };
 
-        #[cfg(feature = "defmt")]
-        defmt::trace!("Write reg [{}] <- {:#x}", defmt::Debug2Format(&reg), value);
+    sim.set_main_events(MainEvents::PROX);
+    let events = iqs.wait_for_events(&mut io1).unwrap();
+    assert!(events.contains(MainEvents::PROX));
 
-        if reg.is_writable() {
-            self.bus
-                .write(self.address as u8, &[reg as u8, value])
-                .map_err(|e| Error::IoError(e))
-        } else {
-            Err(Error::RegisterNotWritable.into())
+    // IO1 stays low for the whole proximity event: no new event, `wait_for_events()` would block
+    assert_eq!(iqs.read_pending_events(&mut io1).unwrap(), None);
+    io1.low = false;
+    assert_eq!(iqs.read_pending_events(&mut io1).unwrap(), None);
+
+    io1.low = true;
+    sim.set_main_events(MainEvents::TOUCH);
+    assert_eq!(
+        iqs.read_pending_events(&mut io1).unwrap(),
+        Some(MainEvents::TOUCH)
+    );
+}
+
+#[test]
+fn change_address_and_discover() {
+    let (sim, mut iqs) = crate::sim::test_device(None);
+    iqs.change_address(I2cAddress::Alt2).unwrap();
+    assert_eq!(iqs.address(), I2cAddress::Alt2);
+    assert_eq!(sim.address(), I2cAddress::Alt2 as u8);
+    assert!(iqs.get_prod_nr().is_ok());
+    assert!(matches!(
+        iqs.change_address(I2cAddress::Test),
+        Err(Error::AddressNotAllowed)
+    ));
+
+    let mut bus = iqs.destroy();
+    assert_eq!(
+        discover(&mut bus),
+        [
+            (I2cAddress::Default, None),
+            (I2cAddress::Test, None),
+            (I2cAddress::Alt1, None),
+            (I2cAddress::Alt2, Some(SoftwareVersion::IQS231A)),
+        ]
+    );
+
+    // back at the programmed address after a reset
+    sim.inject_cold_boot();
+    assert_eq!(discover(&mut bus)[0].1, Some(SoftwareVersion::IQS231A));
+}
+
+#[test]
+fn mode_methods_toggle_only_when_needed() {
+    let (sim, mut iqs) = crate::sim::test_device(None);
+
+    iqs.set_ulp_mode(true).unwrap();
+    iqs.set_ulp_mode(true).unwrap();
+    assert!(sim.ulp_mode());
+    iqs.set_ac_filter(true).unwrap();
+    assert!(sim.ac_filter());
+    iqs.set_ac_filter(false).unwrap();
+    assert!(!sim.ac_filter());
+
+    iqs.set_sensing(false).unwrap();
+    iqs.set_sensing(false).unwrap();
+    assert!(!iqs.is_sensing_enabled().unwrap());
+    iqs.set_sensing(true).unwrap();
+    assert!(iqs.is_sensing_enabled().unwrap());
+
+    iqs.trigger_ati().unwrap();
+    iqs.trigger_ati().unwrap();
+    assert_eq!(sim.ati_count(), 1);
+
+    // a reset reverts the modes, the tracked state follows
+    sim.inject_warm_boot();
+    iqs.read_main_events().unwrap();
+    assert!(!iqs.ulp_mode() && iqs.ac_filter().unwrap());
+    iqs.set_ulp_mode(true).unwrap();
+    assert!(sim.ulp_mode());
+}
+
+#[test]
+fn mode_transitions_follow_the_device() {
+    let (sim, iqs) = crate::sim::test_device(None);
+
+    let mut iqs = iqs.into_sensing_disabled().unwrap();
+    assert!(!iqs.is_sensing_enabled().unwrap());
+    let mut iqs = iqs.into_streaming().unwrap();
+    assert!(iqs.is_sensing_enabled().unwrap());
+
+    let iqs = iqs.into_ulp().unwrap();
+    assert!(sim.ulp_mode());
+    let iqs = iqs.into_streaming().unwrap();
+    assert!(!sim.ulp_mode());
+
+    // a failed transition hands back the driver
+    sim.set_ignore_commands(true);
+    let Err(ModeChangeError {
+        error: Error::ModeChangeNotConfirmed,
+        dev: iqs,
+    }) = iqs.into_sensing_disabled()
+    else {
+        panic!("sensing disabled without the device");
+    };
+    sim.set_ignore_commands(false);
+
+    iqs.into_standalone().unwrap();
+    assert!(sim.is_standalone());
+}
+
+#[test]
+fn managed_mode_enters_the_driver_mode_after_reset() {
+    let (sim, mut iqs) = crate::sim::test_device(None);
+    iqs.read_main_events().unwrap();
+    iqs.enable_managed_mode(Config::default(), false).unwrap();
+
+    let mut iqs = iqs.into_sensing_disabled().unwrap();
+    sim.inject_warm_boot();
+    iqs.read_main_events().unwrap();
+    assert!(!iqs.is_sensing_enabled().unwrap());
+    assert!(iqs.take_reset_event().unwrap().config_restored);
+
+    let mut iqs = iqs.into_streaming().unwrap().into_ulp().unwrap();
+    sim.inject_warm_boot();
+    iqs.read_main_events().unwrap();
+    assert!(sim.ulp_mode() && iqs.ulp_mode());
+}
+
+#[test]
+fn managed_mode_restores_config_after_reset() {
+    let config = Config {
+        touch_threshold: 200,
+        proximity_threshold: ProximityThreshold::Counts10,
+        ..Default::default()
+    };
+
+    let (sim, mut iqs) = crate::sim::test_device(None);
+    // consume the power-on COLD_BOOT, it happened before the configuration was applied
+    iqs.read_main_events().unwrap();
+    iqs.enable_managed_mode(config, true).unwrap();
+    iqs.verify_config(&config).unwrap();
+    assert_eq!(iqs.take_reset_event(), None);
+
+    sim.inject_warm_boot();
+    assert_eq!(sim.register(Register::ProximityThreshold), 0);
+
+    let main_events = iqs.read_main_events().unwrap();
+    assert!(main_events.contains(MainEvents::WARM_BOOT));
+    assert_eq!(
+        iqs.take_reset_event(),
+        Some(ResetEvent {
+            kind: ResetKind::WarmBoot,
+            config_restored: true,
+            ati_triggered: true,
+        })
+    );
+    assert_eq!(sim.ati_count(), 1);
+    iqs.verify_config(&config).unwrap();
+}
+
+#[test]
+fn poll_reports_events_from_sim() {
+    use crate::events::Event;
+
+    let (sim, mut iqs) = crate::sim::test_device(None);
+    assert!(iqs.poll().unwrap().iter().eq([Event::Reset]));
+
+    sim.set_main_events(MainEvents::PROX);
+    sim.set_event_flags(EventFlags::CH0_PROX);
+    assert!(iqs.poll().unwrap().iter().eq([Event::ProximityEnter]));
+    assert!(iqs.poll().unwrap().is_empty());
+}
+
+#[test]
+fn apply_config_keeps_the_address() {
+    let sim = crate::sim::SimIqs231::new().with_address(I2cAddress::Alt1);
+    let mut iqs = Iqs231::new(sim.clone()).with_address(I2cAddress::Alt1);
+
+    iqs.apply_config(&Config::default()).unwrap();
+    assert_eq!(sim.address(), I2cAddress::Alt1 as u8);
+    iqs.verify_config(&Config::default()).unwrap();
+    assert!(iqs.get_prod_nr().is_ok());
+}
+
+#[test]
+fn verify_config_reports_only_differing_registers() {
+    let (sim, mut iqs) = crate::sim::test_device(None);
+
+    // threshold 0, not a valid configuration but a possible register value
+    sim.set_register(Register::Movement, 0x30);
+    match iqs.verify_config(&Config::default()) {
+        Err(Error::ConfigMismatch(mismatch)) => {
+            assert!(mismatch.registers().eq([Register::Movement]))
         }
+        result => panic!("unexpected {:?}", result),
     }
+
+    let invalid = iqs.read_config().unwrap().value;
+    assert!(matches!(
+        iqs.verify_config(&invalid),
+        Err(Error::MovementThresholdOutOfRange)
+    ));
+}
+
+#[test]
+fn managed_mode_follows_address_change_across_reset() {
+    let config = Config {
+        touch_threshold: 200,
+        ..Default::default()
+    };
+
+    let (sim, mut iqs) = crate::sim::test_device(None);
+    iqs.read_main_events().unwrap();
+    iqs.enable_managed_mode(config, false).unwrap();
+    iqs.change_address(I2cAddress::Alt2).unwrap();
+
+    // back at the programmed address, with the OTP defaults
+    sim.inject_warm_boot();
+    assert_eq!(sim.address(), I2cAddress::Default as u8);
+
+    assert!(iqs.get_prod_nr().is_ok());
+    assert_eq!(
+        iqs.take_reset_event(),
+        Some(ResetEvent {
+            kind: ResetKind::WarmBoot,
+            config_restored: true,
+            ati_triggered: false,
+        })
+    );
+    assert_eq!(iqs.address(), I2cAddress::Alt2);
+    assert_eq!(sim.address(), I2cAddress::Alt2 as u8);
+    iqs.verify_config(&config).unwrap();
+}
+
+#[test]
+fn ac_filter_follows_otp_bank1() {
+    let mut config = Config::default();
+    config.otp_bank1.set_ac_filter(AcFilter::Off);
+
+    let (sim, mut iqs) = crate::sim::test_device(None);
+    assert!(iqs.ac_filter().unwrap());
+
+    iqs.enable_managed_mode(config, false).unwrap();
+    assert!(!iqs.ac_filter().unwrap() && !sim.ac_filter());
+    iqs.set_ac_filter(true).unwrap();
+    assert!(sim.ac_filter());
+
+    // the restored configuration switches it off again
+    sim.inject_warm_boot();
+    iqs.read_main_events().unwrap();
+    assert!(!iqs.ac_filter().unwrap() && !sim.ac_filter());
 }
@@ -0,0 +1,589 @@
+use embedded_hal_async::{digital::Wait, i2c::I2c};
+use num_enum::TryFromPrimitive;
+
+use crate::{
+    config::{Config, Mismatch, CONFIG_REGISTERS},
+    device::{touch_threshold_from_reg, touch_threshold_to_reg, I2cAddress},
+    logging::RegBytes,
+    registers::{
+        self, AtiFlags, ChannelMultiplier, Commands, DebugEvents, EventFlags, MainEvents, Movement,
+        OtpBank1, OtpBank2, OtpBank3, ProximityThreshold, QuickRelease, RegValue, Register,
+        SoftwareVersion, SystemFlags, UiFlags,
+    },
+    snapshot::DeviceSnapshot,
+    Error,
+};
+
+/// Async counterpart of [`crate::Iqs231`], built on `embedded_hal_async::i2c::I2c`
+pub struct Iqs231Async<I> {
+    bus: I,
+    address: I2cAddress,
+}
+
+impl<I> Iqs231Async<I> {
+    pub fn new(bus: I) -> Self {
+        Self {
+            bus,
+            address: I2cAddress::default(),
+        }
+    }
+
+    pub fn with_address(self, address: I2cAddress) -> Self {
+        Self { address, ..self }
+    }
+
+    pub fn address(&self) -> I2cAddress {
+        self.address
+    }
+
+    pub fn destroy(self) -> I {
+        self.bus
+    }
+}
+
+impl<E, I> Iqs231Async<I>
+where
+    I: I2c<Error = E>,
+{
+    pub async fn read_main_events(&mut self) -> Result<MainEvents, Error<E>> {
+        let mut rd_buffer = [0u8; 1];
+        self.bus
+            .read(self.address as u8, &mut rd_buffer)
+            .await
+            .map_err(Error::IoError)?;
+
+        Ok(MainEvents::from_bits_retain(rd_buffer[0]))
+    }
+
+    /// Wait (without polling the bus) until IO1 signals a new event, then read the `MainEvents`.
+    /// IO1 is active low and stays low for as long as the event lasts, so this waits for the
+    /// falling edge.
+    pub async fn wait_for_events<P: Wait>(&mut self, io1: &mut P) -> Result<MainEvents, Error<E>> {
+        io1.wait_for_falling_edge()
+            .await
+            .map_err(|_| Error::PinError)?;
+        self.read_main_events().await
+    }
+
+    /// Move the device to `address`, see [`crate::Iqs231::change_address`]
+    pub async fn change_address(&mut self, address: I2cAddress) -> Result<(), Error<E>> {
+        if address == I2cAddress::Test {
+            return Err(Error::AddressNotAllowed);
+        }
+
+        let otp = self.get_otp_bank1().await?.value;
+        self.set_otp_bank1(otp.with_i2c_addr(address)).await?;
+
+        let old = core::mem::replace(&mut self.address, address);
+        if let Err(e) = self.get_prod_nr().await {
+            self.address = old;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub async fn get_prod_nr(&mut self) -> Result<u8, Error<E>> {
+        let prod_nr = self.read_reg(Register::ProductNumber).await?.value;
+        if prod_nr == registers::PRODUCT_NUMBER {
+            Ok(prod_nr)
+        } else {
+            Err(Error::IncorrectProductNumber(prod_nr))
+        }
+    }
+
+    pub async fn get_software_version(&mut self) -> Result<SoftwareVersion, Error<E>> {
+        let ver = self.read_reg(Register::SoftwareVersion).await?.value;
+        SoftwareVersion::try_from_primitive(ver).map_err(|_| Error::UnknownSoftwareVersion(ver))
+    }
+
+    pub async fn set_otp_bank1(&mut self, value: OtpBank1) -> Result<(), Error<E>> {
+        self.write_reg(Register::OtpBank1, value.into_bytes()[0])
+            .await
+    }
+
+    pub async fn get_otp_bank1(&mut self) -> Result<RegValue<OtpBank1>, Error<E>> {
+        let regval = self
+            .read_reg(Register::OtpBank1)
+            .await?
+            .map(|v| OtpBank1::from_bytes([v]));
+        Ok(regval)
+    }
+
+    pub async fn set_otp_bank2(&mut self, value: OtpBank2) -> Result<(), Error<E>> {
+        self.write_reg(Register::OtpBank2, value.into_bytes()[0])
+            .await
+    }
+
+    pub async fn get_otp_bank2(&mut self) -> Result<RegValue<OtpBank2>, Error<E>> {
+        let regval = self
+            .read_reg(Register::OtpBank2)
+            .await?
+            .map(|v| OtpBank2::from_bytes([v]));
+        Ok(regval)
+    }
+
+    pub async fn set_otp_bank3(&mut self, value: OtpBank3) -> Result<(), Error<E>> {
+        self.write_reg(Register::OtpBank3, value.into_bytes()[0])
+            .await
+    }
+
+    pub async fn get_otp_bank3(&mut self) -> Result<RegValue<OtpBank3>, Error<E>> {
+        let regval = self
+            .read_reg(Register::OtpBank3)
+            .await?
+            .map(|v| OtpBank3::from_bytes([v]));
+        Ok(regval)
+    }
+
+    pub async fn set_movement(&mut self, movement: Movement) -> Result<(), Error<E>> {
+        if movement.threshold() == 0 {
+            Err(Error::MovementThresholdOutOfRange)
+        } else {
+            self.write_reg(Register::Movement, movement.into_bytes()[0])
+                .await
+        }
+    }
+
+    pub async fn get_movement(&mut self) -> Result<RegValue<Movement>, Error<E>> {
+        let rv = self.read_reg(Register::Movement).await?;
+        Ok(rv.map(|v| Movement::from_bytes([v])))
+    }
+
+    pub async fn set_touch_threshold(&mut self, threshold: u16) -> Result<(), Error<E>> {
+        let value = touch_threshold_to_reg(threshold)?;
+        self.write_reg(Register::TouchThreshold, value).await
+    }
+
+    pub async fn get_touch_threshold(&mut self) -> Result<RegValue<u16>, Error<E>> {
+        Ok(self
+            .read_reg(Register::TouchThreshold)
+            .await?
+            .map(touch_threshold_from_reg))
+    }
+
+    pub async fn set_proximity_threshold(
+        &mut self,
+        threshold: ProximityThreshold,
+    ) -> Result<(), Error<E>> {
+        self.write_reg(Register::ProximityThreshold, threshold.into())
+            .await
+    }
+
+    pub async fn get_proximity_threshold(
+        &mut self,
+    ) -> Result<RegValue<ProximityThreshold>, Error<E>> {
+        self.read_reg_t(Register::ProximityThreshold).await
+    }
+
+    /// Default 3. Low values are recommended for intended effect.
+    /// Use a higher value when using the feature in a noisy environment.
+    pub async fn set_temp_interference_threshold(&mut self, threshold: u8) -> Result<(), Error<E>> {
+        self.write_reg(Register::TempInterferenceThreshold, threshold)
+            .await
+    }
+
+    pub async fn get_temp_interference_threshold(&mut self) -> Result<RegValue<u8>, Error<E>> {
+        self.read_reg(Register::TempInterferenceThreshold).await
+    }
+
+    pub async fn set_ch0_multipliers(&mut self, mult: ChannelMultiplier) -> Result<(), Error<E>> {
+        self.write_reg(Register::CH0_Multipliers, mult.into_bytes()[0])
+            .await
+    }
+
+    pub async fn get_ch0_multipliers(&mut self) -> Result<RegValue<ChannelMultiplier>, Error<E>> {
+        let regval = self
+            .read_reg(Register::CH0_Multipliers)
+            .await?
+            .map(|val| ChannelMultiplier::from_bytes([val]));
+
+        Ok(regval)
+    }
+
+    pub async fn set_ch0_compensation(&mut self, comp: u8) -> Result<(), Error<E>> {
+        self.write_reg(Register::CH0_Compensation, comp).await
+    }
+
+    pub async fn get_ch0_compensation(&mut self) -> Result<RegValue<u8>, Error<E>> {
+        self.read_reg(Register::CH0_Compensation).await
+    }
+
+    pub async fn set_ch1_multipliers(&mut self, mult: ChannelMultiplier) -> Result<(), Error<E>> {
+        self.write_reg(Register::CH1_Multipliers, mult.into_bytes()[0])
+            .await
+    }
+
+    pub async fn get_ch1_multipliers(&mut self) -> Result<RegValue<ChannelMultiplier>, Error<E>> {
+        let regval = self
+            .read_reg(Register::CH1_Multipliers)
+            .await?
+            .map(|val| ChannelMultiplier::from_bytes([val]));
+
+        Ok(regval)
+    }
+
+    pub async fn set_ch1_compensation(&mut self, comp: u8) -> Result<(), Error<E>> {
+        self.write_reg(Register::CH1_Compensation, comp).await
+    }
+
+    pub async fn get_ch1_compensation(&mut self) -> Result<RegValue<u8>, Error<E>> {
+        self.read_reg(Register::CH1_Compensation).await
+    }
+
+    pub async fn get_debug_events(&mut self) -> Result<DebugEvents, Error<E>> {
+        let value = self.read_reg(Register::DebugEvents).await?.value;
+        Ok(DebugEvents::from_bits_retain(value))
+    }
+
+    pub async fn get_system_flags(&mut self) -> Result<SystemFlags, Error<E>> {
+        let value = self.read_reg(Register::System_Flags).await?.value;
+        Ok(SystemFlags::from_bits_retain(value))
+    }
+
+    pub async fn get_ui_flags(&mut self) -> Result<UiFlags, Error<E>> {
+        let value = self.read_reg(Register::UI_Flags).await?.value;
+        Ok(UiFlags::from_bits_retain(value))
+    }
+
+    pub async fn get_ati_flags(&mut self) -> Result<RegValue<AtiFlags>, Error<E>> {
+        let reg = self
+            .read_reg(Register::ATI_Flags)
+            .await?
+            .map(AtiFlags::from_bits_retain);
+        Ok(reg)
+    }
+
+    pub async fn get_event_flags(&mut self) -> Result<RegValue<EventFlags>, Error<E>> {
+        let reg = self
+            .read_reg(Register::EventFlags)
+            .await?
+            .map(EventFlags::from_bits_retain);
+        Ok(reg)
+    }
+
+    pub async fn set_quick_release(&mut self, quick_rel: QuickRelease) -> Result<(), Error<E>> {
+        self.write_reg(Register::QuickRelease, quick_rel.into_bytes()[0])
+            .await
+    }
+
+    pub async fn get_quick_release(&mut self) -> Result<RegValue<QuickRelease>, Error<E>> {
+        let rv = self.read_reg(Register::QuickRelease).await?;
+        Ok(rv.map(|v| QuickRelease::from_bytes([v])))
+    }
+
+    /// Proximity channel: Filtered count value
+    /// (0-2000)
+    pub async fn get_prox_filtered_count(&mut self) -> Result<RegValue<u16>, Error<E>> {
+        self.read_reg16(Register::CH0_ACF_H).await
+    }
+
+    /// Proximity channel: Reference count value (Long term average)
+    /// (0-2000)
+    pub async fn get_prox_reference_count(&mut self) -> Result<RegValue<u16>, Error<E>> {
+        self.read_reg16(Register::CH0_LTA_H).await
+    }
+
+    /// Proximity channel: Quick release detect reference value
+    /// (0-2000)
+    pub async fn get_prox_quick_release_detect_reference(
+        &mut self,
+    ) -> Result<RegValue<u16>, Error<E>> {
+        self.read_reg16(Register::CH0_QRD_H).await
+    }
+
+    /// Movement channel: Filtered count value
+    /// (0-2000)
+    pub async fn get_move_filtered_count(&mut self) -> Result<RegValue<u16>, Error<E>> {
+        self.read_reg16(Register::CH1_ACF_H).await
+    }
+
+    /// Movement channel: Upper reference count value
+    /// (0-2000)
+    pub async fn get_move_upper_reference_count(&mut self) -> Result<RegValue<u16>, Error<E>> {
+        self.read_reg16(Register::CH1_UMOV_H).await
+    }
+
+    /// Movement channel: Lower reference count value
+    /// (0-2000)
+    pub async fn get_move_lower_reference_count(&mut self) -> Result<RegValue<u16>, Error<E>> {
+        self.read_reg16(Register::CH1_LMOV_H).await
+    }
+
+    /// Temperature channel: Unfiltered count value (if temperature feature enabled)
+    /// (0-2000)
+    pub async fn get_move_unfiltered_count(&mut self) -> Result<RegValue<u16>, Error<E>> {
+        self.read_reg16(Register::CH1_RAW_H).await
+    }
+
+    /// Movement channel temperature reference (a previous value of temperature channel)
+    /// (0-2000)
+    pub async fn get_temp_reference(&mut self) -> Result<RegValue<u16>, Error<E>> {
+        self.read_reg16(Register::Temperature_H).await
+    }
+
+    /// Countdown timer to give active feedback on the time-out. Movement events will reset this timer
+    /// (0 – 255) × 100ms | Timer range: 0 – 90min
+    pub async fn get_lta_halt_timer(&mut self) -> Result<RegValue<u16>, Error<E>> {
+        self.read_reg16(Register::LtaHaltTimer_H).await
+    }
+
+    /// Countdown timer to give active feedback on the fixed 5sec time-out when in filter halt mode
+    /// (0 – 50) x 100ms | Timer range: 0 – 5 seconds
+    pub async fn get_filter_halt_timer(&mut self) -> Result<RegValue<u8>, Error<E>> {
+        self.read_reg(Register::FilterHaltTimer).await
+    }
+
+    /// Countdown timer to signal when a read operation is done on IO2
+    /// (0 – 10) x 100ms | Timer range: 0 – 1 seconds
+    pub async fn get_timer_read_input(&mut self) -> Result<RegValue<u8>, Error<E>> {
+        self.read_reg(Register::TimerReadInput).await
+    }
+
+    /// Countdown timer to give active feedback on the time until re-calibration is attempted after ATI-error
+    /// (0 – 255) × 100ms | Timer range: 0 – 25s
+    pub async fn get_timer_redo_ati(&mut self) -> Result<RegValue<u8>, Error<E>> {
+        self.read_reg(Register::TimerRedoAti).await
+    }
+
+    /// Read the complete register map (`ProductNumber` up to `TimerRedoAti`) in a single burst
+    pub async fn read_all(&mut self) -> Result<RegValue<DeviceSnapshot>, Error<E>> {
+        let regs = self
+            .read_burst::<{ registers::REGISTER_COUNT }>(Register::ProductNumber)
+            .await?;
+        Ok(regs.map(|r| DeviceSnapshot::from_registers(&r)))
+    }
+
+    /// Write all configuration registers (see `config::CONFIG_REGISTERS`).
+    /// The configuration is validated before anything is written.
+    ///
+    /// `OtpBank1::i2c_addr` is kept at the address the driver talks to, use `change_address()`
+    /// to move the device.
+    pub async fn apply_config(&mut self, config: &Config) -> Result<(), Error<E>> {
+        let values = config.with_i2c_addr(self.address).to_registers()?;
+        for (reg, value) in CONFIG_REGISTERS.into_iter().zip(values) {
+            self.write_reg(reg, value).await?;
+        }
+        Ok(())
+    }
+
+    /// Read all configuration registers in a single burst
+    pub async fn read_config(&mut self) -> Result<RegValue<Config>, Error<E>> {
+        let regs = self
+            .read_burst::<{ CONFIG_REGISTERS.len() }>(CONFIG_REGISTERS[0])
+            .await?;
+        Ok(regs.map(|r| Config::from_registers(&r)))
+    }
+
+    /// Read back the configuration and compare it with `config`.
+    /// Returns `Error::ConfigMismatch` listing the differing registers if they do not match, the
+    /// range errors of `Config::to_registers()` if `config` is invalid.
+    /// `OtpBank1::i2c_addr` is not compared, see `apply_config()`.
+    pub async fn verify_config(&mut self, config: &Config) -> Result<(), Error<E>> {
+        // an invalid configuration is reported as such, not as a mismatch
+        let expected = config.with_i2c_addr(self.address).to_registers()?;
+        let actual = self
+            .read_burst::<{ CONFIG_REGISTERS.len() }>(CONFIG_REGISTERS[0])
+            .await?
+            .value;
+        match Mismatch::between(&expected, &actual) {
+            Some(mismatch) => Err(Error::ConfigMismatch(mismatch)),
+            None => Ok(()),
+        }
+    }
+
+    /// Use this function (taking ownership of device) to put device in standalone mode
+    /// returns the the I²C bus
+    pub async fn into_standalone(mut self) -> Result<I, Error<E>> {
+        self.write_reg(Register::Commands, Commands::STANDALONE.bits())
+            .await?;
+        Ok(self.destroy())
+    }
+
+    /// Send command(s)
+    /// Sending command "STANDALONE" ("WARM_BOOT") NOT allowed, as this disables i2c on the device.
+    /// use `into_standalone()` to issue the `STANDALONE` command
+    pub async fn send_commands(&mut self, commands: Commands) -> Result<(), Error<E>> {
+        if commands.contains(Commands::STANDALONE) {
+            warn!("{:?} refused, use `into_standalone()`", commands);
+            Err(Error::ShutdownCommandNotAllowed)
+        } else {
+            debug!("Send {:?}", commands);
+            self.write_reg(Register::Commands, commands.bits()).await
+        }
+    }
+
+    async fn read_reg16(
+        &mut self,
+        register: impl Into<Register>,
+    ) -> Result<RegValue<u16>, Error<E>> {
+        Ok(self
+            .read_burst::<2>(register)
+            .await?
+            .map(u16::from_be_bytes))
+    }
+
+    /// Read register converted into the specified type (using `From<u8>`)
+    async fn read_reg_t<T: From<u8>>(
+        &mut self,
+        register: impl Into<Register>,
+    ) -> Result<RegValue<T>, Error<E>> {
+        self.read_reg(register).await.map(|rv| rv.map(T::from))
+    }
+
+    async fn read_reg(&mut self, register: impl Into<Register>) -> Result<RegValue<u8>, Error<E>> {
+        Ok(self.read_burst::<1>(register).await?.map(|[v]| v))
+    }
+
+    /// Read `N` consecutive registers starting at `register` in a single I²C transaction.
+    /// The device auto-increments the register address after the leading `MainEvents` byte,
+    /// so multi-byte values can not tear between reads.
+    async fn read_burst<const N: usize>(
+        &mut self,
+        register: impl Into<Register>,
+    ) -> Result<RegValue<[u8; N]>, Error<E>> {
+        const { assert!(N > 0 && N <= registers::REGISTER_COUNT) };
+
+        let reg: Register = register.into();
+        Register::from_u8(reg as u8 + (N as u8 - 1))?;
+
+        let mut rd_buffer = [0u8; registers::REGISTER_COUNT + 1];
+        let rd_buffer = &mut rd_buffer[..=N];
+
+        self.bus
+            .write_read(self.address as u8, &[reg as u8], rd_buffer)
+            .await
+            .map_err(|e| {
+                warn!("Read {:?} at {:?} failed", reg, self.address);
+                Error::IoError(e)
+            })?;
+
+        let regval = RegValue::from_burst(rd_buffer);
+        trace!(
+            "Read {:?} -> {:?} ({:?})",
+            reg,
+            RegBytes {
+                register: reg,
+                bytes: &regval.value,
+            },
+            regval.main_events
+        );
+        Ok(regval)
+    }
+
+    async fn write_reg(
+        &mut self,
+        register: impl Into<Register>,
+        value: u8,
+    ) -> Result<(), Error<E>> {
+        let reg: Register = register.into();
+
+        trace!(
+            "Write {:?} <- {:?}",
+            reg,
+            RegBytes {
+                register: reg,
+                bytes: &[value],
+            }
+        );
+
+        if reg.is_writable() {
+            self.bus
+                .write(self.address as u8, &[reg as u8, value])
+                .await
+                .map_err(|e| {
+                    warn!("Write {:?} at {:?} failed", reg, self.address);
+                    Error::IoError(e)
+                })
+        } else {
+            warn!("Register {:?} is not writable", reg);
+            Err(Error::RegisterNotWritable)
+        }
+    }
+}
+
+/// IO1 event pin driven by the test: level waits complete on the level, edge waits only on a
+/// queued falling edge
+#[cfg(all(test, feature = "eh1"))]
+struct TestPin {
+    low: bool,
+    falling_edges: usize,
+}
+
+#[cfg(all(test, feature = "eh1"))]
+impl embedded_hal_1::digital::ErrorType for TestPin {
+    type Error = core::convert::Infallible;
+}
+
+#[cfg(all(test, feature = "eh1"))]
+impl Wait for TestPin {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        if self.low {
+            core::future::pending::<()>().await;
+        }
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        if !self.low {
+            core::future::pending::<()>().await;
+        }
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        core::future::pending().await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        if self.falling_edges == 0 {
+            core::future::pending::<()>().await;
+        }
+        self.falling_edges -= 1;
+        self.low = true;
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_falling_edge().await
+    }
+}
+
+/// Poll `future` once
+#[cfg(all(test, feature = "eh1"))]
+fn poll_once<F: core::future::Future>(future: F) -> core::task::Poll<F::Output> {
+    use core::task::{Context, RawWaker, RawWakerVTable, Waker};
+
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(
+        |_| RawWaker::new(core::ptr::null(), &VTABLE),
+        |_| {},
+        |_| {},
+        |_| {},
+    );
+    // SAFETY: the vtable functions do nothing, so any data pointer is fine
+    let waker = unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) };
+    let mut future = core::pin::pin!(future);
+    future.as_mut().poll(&mut Context::from_waker(&waker))
+}
+
+#[cfg(feature = "eh1")]
+#[test]
+fn wait_for_events_waits_for_the_falling_edge() {
+    use core::task::Poll;
+
+    let sim = crate::sim::SimIqs231::new();
+    let mut iqs = Iqs231Async::new(sim.clone());
+    let mut io1 = TestPin {
+        low: false,
+        falling_edges: 1,
+    };
+
+    sim.set_main_events(MainEvents::PROX);
+    assert!(matches!(
+        poll_once(iqs.wait_for_events(&mut io1)),
+        Poll::Ready(Ok(events)) if events.contains(MainEvents::PROX)
+    ));
+
+    // IO1 still low from the same event: wait for the next one
+    assert!(io1.low);
+    assert!(poll_once(iqs.wait_for_events(&mut io1)).is_pending());
+}
@@ -0,0 +1,231 @@
+//! Edge detection on top of the raw (level) event flags, see `Iqs231::poll()`
+use crate::registers::{DebugEvents, EventFlags, MainEvents};
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    ProximityEnter,
+    ProximityExit,
+    TouchDown,
+    TouchUp,
+    MovementStart,
+    MovementStop,
+    QuickRelease,
+    AtiError,
+    Reset,
+}
+
+impl Event {
+    const ALL: [Event; 9] = [
+        Event::ProximityEnter,
+        Event::ProximityExit,
+        Event::TouchDown,
+        Event::TouchUp,
+        Event::MovementStart,
+        Event::MovementStop,
+        Event::QuickRelease,
+        Event::AtiError,
+        Event::Reset,
+    ];
+
+    fn mask(self) -> u16 {
+        1 << self as u8
+    }
+}
+
+/// Set of events produced by a single poll
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Events(u16);
+
+impl Events {
+    pub fn contains(&self, event: Event) -> bool {
+        self.0 & event.mask() != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn insert(&mut self, event: Event) {
+        self.0 |= event.mask();
+    }
+
+    /// Iterate over the events in the set, in declaration order of `Event`
+    pub fn iter(&self) -> impl Iterator<Item = Event> {
+        let set = *self;
+        Event::ALL.into_iter().filter(move |e| set.contains(*e))
+    }
+}
+
+/// Level that only changes after being observed for `debounce` consecutive samples
+#[derive(Clone, Copy, Debug, Default)]
+struct Debounced {
+    state: bool,
+    count: u8,
+}
+
+impl Debounced {
+    /// Returns the new state on a (debounced) transition
+    fn update(&mut self, level: bool, debounce: u8) -> Option<bool> {
+        if level == self.state {
+            self.count = 0;
+            return None;
+        }
+
+        self.count += 1;
+        if self.count >= debounce {
+            self.state = level;
+            self.count = 0;
+            Some(level)
+        } else {
+            None
+        }
+    }
+}
+
+/// Turns successive `MainEvents`, `EventFlags` and `DebugEvents` samples into `Events`.
+///
+/// Proximity, touch and movement are levels and are debounced, the other flags are reported on
+/// their rising edge.
+#[derive(Clone, Copy, Debug)]
+pub struct EventTracker {
+    debounce: u8,
+    prox: Debounced,
+    touch: Debounced,
+    movement: Debounced,
+    quick_release: bool,
+    ati_error: bool,
+    reset: bool,
+}
+
+impl Default for EventTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventTracker {
+    pub fn new() -> Self {
+        Self {
+            debounce: 1,
+            prox: Debounced::default(),
+            touch: Debounced::default(),
+            movement: Debounced::default(),
+            quick_release: false,
+            ati_error: false,
+            reset: false,
+        }
+    }
+
+    /// Number of consecutive samples a proximity/touch/movement level must be seen before an
+    /// event is emitted (minimum and default 1)
+    pub fn with_debounce(self, samples: u8) -> Self {
+        Self {
+            debounce: samples.max(1),
+            ..self
+        }
+    }
+
+    pub fn update(&mut self, main: MainEvents, flags: EventFlags, debug: DebugEvents) -> Events {
+        let mut events = Events::default();
+
+        let prox = main.contains(MainEvents::PROX) || flags.contains(EventFlags::CH0_PROX);
+        match self.prox.update(prox, self.debounce) {
+            Some(true) => events.insert(Event::ProximityEnter),
+            Some(false) => events.insert(Event::ProximityExit),
+            None => {}
+        }
+
+        let touch = main.contains(MainEvents::TOUCH) || flags.contains(EventFlags::CH0_TOUCH);
+        match self.touch.update(touch, self.debounce) {
+            Some(true) => events.insert(Event::TouchDown),
+            Some(false) => events.insert(Event::TouchUp),
+            None => {}
+        }
+
+        let movement =
+            flags.contains(EventFlags::CH1_MOVEMENT) || debug.contains(DebugEvents::MOVEMENT);
+        match self.movement.update(movement, self.debounce) {
+            Some(true) => events.insert(Event::MovementStart),
+            Some(false) => events.insert(Event::MovementStop),
+            None => {}
+        }
+
+        let quick_release =
+            main.contains(MainEvents::RELEASE) || debug.contains(DebugEvents::QUICK_RELEASE);
+        if quick_release && !self.quick_release {
+            events.insert(Event::QuickRelease);
+        }
+        self.quick_release = quick_release;
+
+        let ati_error = debug.contains(DebugEvents::ATI_ERROR)
+            || flags.intersects(EventFlags::CH0_ATI_ERROR | EventFlags::CH1_ATI_ERROR);
+        if ati_error && !self.ati_error {
+            events.insert(Event::AtiError);
+        }
+        self.ati_error = ati_error;
+
+        let reset = main.intersects(MainEvents::COLD_BOOT | MainEvents::WARM_BOOT);
+        if reset && !self.reset {
+            events.insert(Event::Reset);
+        }
+        self.reset = reset;
+
+        events
+    }
+}
+
+#[test]
+fn tracker_reports_edges_once() {
+    let mut tracker = EventTracker::new();
+
+    let events = tracker.update(MainEvents::PROX, EventFlags::CH0_PROX, DebugEvents::empty());
+    assert!(events.iter().eq([Event::ProximityEnter]));
+
+    let events = tracker.update(MainEvents::PROX, EventFlags::CH0_PROX, DebugEvents::empty());
+    assert!(events.is_empty());
+
+    let events = tracker.update(
+        MainEvents::PROX | MainEvents::TOUCH | MainEvents::COLD_BOOT,
+        EventFlags::CH0_PROX | EventFlags::CH0_TOUCH,
+        DebugEvents::ENTER_MOV_DETECT | DebugEvents::MOVEMENT,
+    );
+    assert!(events
+        .iter()
+        .eq([Event::TouchDown, Event::MovementStart, Event::Reset]));
+
+    let events = tracker.update(
+        MainEvents::RELEASE,
+        EventFlags::empty(),
+        DebugEvents::empty(),
+    );
+    assert!(events.iter().eq([
+        Event::ProximityExit,
+        Event::TouchUp,
+        Event::MovementStop,
+        Event::QuickRelease
+    ]));
+}
+
+#[test]
+fn tracker_debounces_levels() {
+    let mut tracker = EventTracker::new().with_debounce(3);
+    let prox = (MainEvents::PROX, EventFlags::CH0_PROX, DebugEvents::empty());
+
+    assert!(tracker.update(prox.0, prox.1, prox.2).is_empty());
+    assert!(tracker.update(prox.0, prox.1, prox.2).is_empty());
+    // glitch restarts the debounce count
+    assert!(tracker
+        .update(
+            MainEvents::empty(),
+            EventFlags::empty(),
+            DebugEvents::empty()
+        )
+        .is_empty());
+    assert!(tracker.update(prox.0, prox.1, prox.2).is_empty());
+    assert!(tracker.update(prox.0, prox.1, prox.2).is_empty());
+    assert!(tracker
+        .update(prox.0, prox.1, prox.2)
+        .contains(Event::ProximityEnter));
+}
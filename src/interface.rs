@@ -0,0 +1,144 @@
+//! Bus abstraction used by the driver.
+//!
+//! `Iqs231<I>` talks to the device through `I2cInterface`. It is implemented for every bus
+//! implementing the embedded-hal v0.2 blocking I²C traits, so existing HALs work as before.
+//! With the `eh1` feature enabled, an embedded-hal v1.0 `I2c` bus can be used by wrapping it in
+//! [`Eh1`]. The same goes for the IO1 event pin (`EventPin`) and delays (`Delay`).
+use embedded_hal::{
+    blocking::{
+        delay::DelayMs,
+        i2c::{Read, Write, WriteRead},
+    },
+    digital::v2::InputPin,
+};
+
+/// Minimal set of I²C transactions needed to talk to the IQS231
+pub trait I2cInterface {
+    type Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error>;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error>;
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error>;
+}
+
+impl<E, I> I2cInterface for I
+where
+    I: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+{
+    type Error = E;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), E> {
+        Read::read(self, address, buffer)
+    }
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), E> {
+        Write::write(self, address, bytes)
+    }
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), E> {
+        WriteRead::write_read(self, address, bytes, buffer)
+    }
+}
+
+/// Input connected to the (active low) IO1 event output of the IQS231
+pub trait EventPin {
+    type Error;
+
+    fn is_low(&mut self) -> Result<bool, Self::Error>;
+}
+
+impl<P> EventPin for P
+where
+    P: InputPin,
+{
+    type Error = P::Error;
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        InputPin::is_low(self)
+    }
+}
+
+/// Millisecond delay, used for periodic sampling
+pub trait Delay {
+    fn delay_ms(&mut self, ms: u32);
+}
+
+impl<D> Delay for D
+where
+    D: DelayMs<u32>,
+{
+    fn delay_ms(&mut self, ms: u32) {
+        DelayMs::delay_ms(self, ms)
+    }
+}
+
+/// Wrapper to use embedded-hal v1.0 peripherals with the driver.
+///
+/// ```ignore
+/// let iqs = Iqs231::new(Eh1(i2c));
+/// ```
+#[cfg(feature = "eh1")]
+#[derive(Debug)]
+pub struct Eh1<T>(pub T);
+
+#[cfg(feature = "eh1")]
+impl<T> Eh1<T> {
+    /// Return the wrapped peripheral
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+#[cfg(feature = "eh1")]
+impl<I> I2cInterface for Eh1<I>
+where
+    I: embedded_hal_1::i2c::I2c,
+{
+    type Error = I::Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.read(address, buffer)
+    }
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.write(address, bytes)
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.0.write_read(address, bytes, buffer)
+    }
+}
+
+#[cfg(feature = "eh1")]
+impl<P> EventPin for Eh1<P>
+where
+    P: embedded_hal_1::digital::InputPin,
+{
+    type Error = P::Error;
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.0.is_low()
+    }
+}
+
+#[cfg(feature = "eh1")]
+impl<D> Delay for Eh1<D>
+where
+    D: embedded_hal_1::delay::DelayNs,
+{
+    fn delay_ms(&mut self, ms: u32) {
+        self.0.delay_ms(ms)
+    }
+}
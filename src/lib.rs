@@ -1,11 +1,39 @@
-#![no_std]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
+#[macro_use]
+mod logging;
+
+pub mod ati;
+#[cfg(any(feature = "std", test))]
+pub mod cli;
+pub mod config;
+#[cfg(feature = "defmt")]
+mod defmt_impls;
 pub mod device;
+#[cfg(feature = "async")]
+pub mod device_async;
+pub mod events;
+pub mod interface;
+pub mod metrics;
+pub mod mode;
+pub mod record;
 pub mod registers;
+pub mod sampling;
+#[cfg(feature = "serde")]
+mod serde_impls;
+#[cfg(any(feature = "sim", test))]
+pub mod sim;
+pub mod snapshot;
+pub mod tuning;
 
-pub use device::Iqs231;
+pub use device::{discover, Iqs231};
+#[cfg(feature = "async")]
+pub use device_async::Iqs231Async;
+#[cfg(feature = "eh1")]
+pub use interface::Eh1;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error<IE> {
     /// All I²C bus and comms errors are wrapped here
     IoError(IE),
@@ -24,14 +52,47 @@ pub enum Error<IE> {
     /// Use `into_standalone()` to issue this the `STANDALONE` command,
     ShutdownCommandNotAllowed,
 
+    /// `I2cAddress::Test` can not be used for normal operation
+    AddressNotAllowed,
+
     /// touch threshold should be 4..=1024
     TouchThresholdOutOfRange,
+
+    /// movement threshold should be 1..=15
+    MovementThresholdOutOfRange,
+
+    /// The device did not confirm a requested mode change (see `Iqs231::into_sensing_disabled()`
+    /// and `Iqs231::trigger_ati()`)
+    ModeChangeNotConfirmed,
+
+    /// Reading the IO1 event pin failed
+    PinError,
+
+    /// Configuration read back from the device differs from the expected configuration
+    ConfigMismatch(config::Mismatch),
 }
 
-// Allow for quenching the error in a Result<_,()>
-impl<E> From<Error<E>> for ()
-{
-    fn from(value: Error<E>) -> Self {
-        ()
+/// Failed mode transition (see `mode`), handing back the driver in its previous mode
+pub struct ModeChangeError<E, D> {
+    pub error: Error<E>,
+    pub dev: D,
+}
+
+impl<E: core::fmt::Debug, D> core::fmt::Debug for ModeChangeError<E, D> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ModeChangeError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E, D> From<ModeChangeError<E, D>> for Error<E> {
+    fn from(e: ModeChangeError<E, D>) -> Self {
+        e.error
     }
 }
+
+// Allow for quenching the error in a Result<_,()>
+impl<E> From<Error<E>> for () {
+    fn from(_: Error<E>) -> Self {}
+}
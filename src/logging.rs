@@ -0,0 +1,165 @@
+//! Internal logging macros, forwarding to `defmt` and/or `log` depending on the enabled features.
+//!
+//! Format strings must be valid for both: use `{:?}` for the crate types (`defmt::Format` /
+//! `Debug`), `{}` for plain integers and bools and `{:#x}` for hex.
+use crate::registers::{
+    AtiFlags, ChannelMultiplier, Commands, DebugEvents, EventFlags, Movement, OtpBank1, OtpBank2,
+    OtpBank3, QuickRelease, Register, SystemFlags, UiFlags,
+};
+
+macro_rules! log_event {
+    ($level:ident, $fmt:literal $(, $arg:expr)* $(,)?) => {{
+        #[cfg(feature = "defmt")]
+        defmt::$level!($fmt $(, $arg)*);
+        #[cfg(feature = "log")]
+        log::$level!($fmt $(, $arg)*);
+        #[cfg(not(any(feature = "defmt", feature = "log")))]
+        let _ = ($(&$arg,)*);
+    }};
+}
+
+macro_rules! trace {
+    ($($t:tt)*) => { log_event!(trace, $($t)*) };
+}
+
+macro_rules! debug {
+    ($($t:tt)*) => { log_event!(debug, $($t)*) };
+}
+
+macro_rules! info {
+    ($($t:tt)*) => { log_event!(info, $($t)*) };
+}
+
+macro_rules! warn {
+    ($($t:tt)*) => { log_event!(warn, $($t)*) };
+}
+
+/// Register contents decoded for logging: flags and bitfields by their type, 16-bit values as
+/// one number, anything else as raw bytes
+pub(crate) struct RegBytes<'a> {
+    pub register: Register,
+    pub bytes: &'a [u8],
+}
+
+enum Decoded<'a> {
+    Commands(Commands),
+    DebugEvents(DebugEvents),
+    SystemFlags(SystemFlags),
+    UiFlags(UiFlags),
+    AtiFlags(AtiFlags),
+    EventFlags(EventFlags),
+    OtpBank1(OtpBank1),
+    OtpBank2(OtpBank2),
+    OtpBank3(OtpBank3),
+    QuickRelease(QuickRelease),
+    Movement(Movement),
+    Multipliers(ChannelMultiplier),
+    Byte(u8),
+    Word(u16),
+    Bytes(&'a [u8]),
+}
+
+impl RegBytes<'_> {
+    fn decode(&self) -> Decoded<'_> {
+        let Self { register, bytes } = *self;
+        let &[b] = bytes else {
+            return match (register, bytes) {
+                (
+                    Register::CH0_ACF_H
+                    | Register::CH0_LTA_H
+                    | Register::CH0_QRD_H
+                    | Register::CH1_ACF_H
+                    | Register::CH1_UMOV_H
+                    | Register::CH1_LMOV_H
+                    | Register::CH1_RAW_H
+                    | Register::Temperature_H
+                    | Register::LtaHaltTimer_H,
+                    &[h, l],
+                ) => Decoded::Word(u16::from_be_bytes([h, l])),
+                _ => Decoded::Bytes(bytes),
+            };
+        };
+
+        match register {
+            Register::Commands => Decoded::Commands(Commands::from_bits_retain(b)),
+            Register::DebugEvents => Decoded::DebugEvents(DebugEvents::from_bits_retain(b)),
+            Register::System_Flags => Decoded::SystemFlags(SystemFlags::from_bits_retain(b)),
+            Register::UI_Flags => Decoded::UiFlags(UiFlags::from_bits_retain(b)),
+            Register::ATI_Flags => Decoded::AtiFlags(AtiFlags::from_bits_retain(b)),
+            Register::EventFlags => Decoded::EventFlags(EventFlags::from_bits_retain(b)),
+            Register::OtpBank1 => Decoded::OtpBank1(OtpBank1::from_bytes([b])),
+            Register::OtpBank2 => Decoded::OtpBank2(OtpBank2::from_bytes([b])),
+            Register::OtpBank3 => Decoded::OtpBank3(OtpBank3::from_bytes([b])),
+            Register::QuickRelease => Decoded::QuickRelease(QuickRelease::from_bytes([b])),
+            Register::Movement => Decoded::Movement(Movement::from_bytes([b])),
+            Register::CH0_Multipliers | Register::CH1_Multipliers => {
+                Decoded::Multipliers(ChannelMultiplier::from_bytes([b]))
+            }
+            _ => Decoded::Byte(b),
+        }
+    }
+}
+
+impl core::fmt::Debug for RegBytes<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.decode() {
+            Decoded::Commands(v) => write!(f, "{v:?}"),
+            Decoded::DebugEvents(v) => write!(f, "{v:?}"),
+            Decoded::SystemFlags(v) => write!(f, "{v:?}"),
+            Decoded::UiFlags(v) => write!(f, "{v:?}"),
+            Decoded::AtiFlags(v) => write!(f, "{v:?}"),
+            Decoded::EventFlags(v) => write!(f, "{v:?}"),
+            Decoded::OtpBank1(v) => write!(f, "{v:?}"),
+            Decoded::OtpBank2(v) => write!(f, "{v:?}"),
+            Decoded::OtpBank3(v) => write!(f, "{v:?}"),
+            Decoded::QuickRelease(v) => write!(f, "{v:?}"),
+            Decoded::Movement(v) => write!(f, "{v:?}"),
+            Decoded::Multipliers(v) => write!(f, "{v:?}"),
+            Decoded::Byte(v) => write!(f, "{v:#04x}"),
+            Decoded::Word(v) => write!(f, "{v}"),
+            Decoded::Bytes(v) => write!(f, "{v:02x?}"),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for RegBytes<'_> {
+    fn format(&self, f: defmt::Formatter) {
+        match self.decode() {
+            Decoded::Commands(v) => defmt::write!(f, "{}", v),
+            Decoded::DebugEvents(v) => defmt::write!(f, "{}", v),
+            Decoded::SystemFlags(v) => defmt::write!(f, "{}", v),
+            Decoded::UiFlags(v) => defmt::write!(f, "{}", v),
+            Decoded::AtiFlags(v) => defmt::write!(f, "{}", v),
+            Decoded::EventFlags(v) => defmt::write!(f, "{}", v),
+            Decoded::OtpBank1(v) => defmt::write!(f, "{}", v),
+            Decoded::OtpBank2(v) => defmt::write!(f, "{}", v),
+            Decoded::OtpBank3(v) => defmt::write!(f, "{}", v),
+            Decoded::QuickRelease(v) => defmt::write!(f, "{}", v),
+            Decoded::Movement(v) => defmt::write!(f, "{}", v),
+            Decoded::Multipliers(v) => defmt::write!(f, "{}", v),
+            Decoded::Byte(v) => defmt::write!(f, "{=u8:#x}", v),
+            Decoded::Word(v) => defmt::write!(f, "{=u16}", v),
+            Decoded::Bytes(v) => defmt::write!(f, "{=[u8]:#x}", v),
+        }
+    }
+}
+
+#[test]
+fn register_bytes_are_decoded() {
+    use std::format;
+
+    let reg = |register, bytes| format!("{:?}", RegBytes { register, bytes });
+
+    assert_eq!(
+        reg(Register::UI_Flags, &[0x02]),
+        format!("{:?}", UiFlags::from_bits_retain(0x02))
+    );
+    assert_eq!(
+        reg(Register::Movement, &[0x34]),
+        format!("{:?}", Movement::default())
+    );
+    assert_eq!(reg(Register::CH0_ACF_H, &[0x03, 0xe8]), "1000");
+    assert_eq!(reg(Register::TouchThreshold, &[0x07]), "0x07");
+    assert_eq!(reg(Register::ProductNumber, &[0x40, 0x06]), "[40, 06]");
+}
@@ -0,0 +1,259 @@
+//! Derived values to tune against: proximity delta, threshold margins, movement window and noise.
+//!
+//! The counts of the IQS231 decrease when an object approaches the electrode, so the proximity
+//! delta is `LTA − ACF` (positive on approach), which is what the device compares against the
+//! proximity and touch thresholds.
+use crate::{sampling::CountSample, snapshot::DeviceSnapshot};
+
+/// Proximity delta (`CH0_LTA − CH0_ACF`), positive when an object approaches the electrode
+pub fn proximity_delta(reference: u16, filtered: u16) -> i32 {
+    reference as i32 - filtered as i32
+}
+
+/// Counts the delta has left before reaching `threshold` (negative when past the threshold)
+pub fn threshold_margin(delta: i32, threshold: u16) -> i32 {
+    threshold as i32 - delta
+}
+
+/// Movement window width (`CH1_UMOV − CH1_LMOV`)
+pub fn movement_window(upper: u16, lower: u16) -> i32 {
+    upper as i32 - lower as i32
+}
+
+/// Derived values of a single reading, see `Iqs231::read_metrics()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Metrics {
+    /// Proximity delta (`LTA − ACF`)
+    pub delta: i32,
+    /// Counts left before proximity is detected (negative when in proximity)
+    pub prox_margin: i32,
+    /// Counts left before touch is detected (negative when touched)
+    pub touch_margin: i32,
+    /// Movement window width (`UMOV − LMOV`)
+    pub movement_window: i32,
+}
+
+impl Metrics {
+    fn new(filtered: u16, reference: u16, prox: u16, touch: u16, umov: u16, lmov: u16) -> Self {
+        let delta = proximity_delta(reference, filtered);
+        Self {
+            delta,
+            prox_margin: threshold_margin(delta, prox),
+            touch_margin: threshold_margin(delta, touch),
+            movement_window: movement_window(umov, lmov),
+        }
+    }
+}
+
+impl From<&CountSample> for Metrics {
+    fn from(s: &CountSample) -> Self {
+        Self::new(
+            s.prox_filtered,
+            s.prox_reference,
+            s.prox_threshold,
+            s.touch_threshold,
+            s.move_upper_reference,
+            s.move_lower_reference,
+        )
+    }
+}
+
+impl From<&DeviceSnapshot> for Metrics {
+    fn from(s: &DeviceSnapshot) -> Self {
+        Self::new(
+            s.prox_filtered_count,
+            s.prox_reference_count,
+            s.proximity_threshold.counts(),
+            s.touch_threshold,
+            s.move_upper_reference_count,
+            s.move_lower_reference_count,
+        )
+    }
+}
+
+/// Summary of a series of values, see `NoiseEstimator`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NoiseStats {
+    pub samples: u32,
+    pub mean: i32,
+    pub min: i32,
+    pub max: i32,
+    /// Standard deviation, rounded to whole counts
+    pub std_dev: u32,
+}
+
+impl NoiseStats {
+    pub fn peak_to_peak(&self) -> u32 {
+        self.max.abs_diff(self.min)
+    }
+}
+
+/// Running mean / min / max / standard deviation of repeated readings (e.g. the delta of an
+/// untouched sensor). Integer only, usable without `std`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoiseEstimator {
+    samples: u32,
+    sum: i64,
+    sum_sq: i64,
+    min: i32,
+    max: i32,
+}
+
+impl NoiseEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, value: i32) {
+        if self.samples == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.samples += 1;
+        self.sum += value as i64;
+        self.sum_sq += value as i64 * value as i64;
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn stats(&self) -> NoiseStats {
+        if self.samples == 0 {
+            return NoiseStats::default();
+        }
+
+        let n = self.samples as i64;
+        // population variance: (Σx² − (Σx)²/n) / n, computed as (nΣx² − (Σx)²) / n²
+        let variance = (n * self.sum_sq - self.sum * self.sum) / (n * n);
+        NoiseStats {
+            samples: self.samples,
+            mean: div_round(self.sum, n) as i32,
+            min: self.min,
+            max: self.max,
+            std_dev: isqrt_round(variance.max(0) as u64) as u32,
+        }
+    }
+}
+
+impl FromIterator<i32> for NoiseEstimator {
+    fn from_iter<T: IntoIterator<Item = i32>>(iter: T) -> Self {
+        let mut estimator = Self::new();
+        iter.into_iter().for_each(|v| estimator.push(v));
+        estimator
+    }
+}
+
+/// Noise on the proximity channel, see `Iqs231::estimate_noise()`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NoiseReport {
+    /// Proximity filtered count (ACF)
+    pub filtered: NoiseStats,
+    /// Proximity delta (`LTA − ACF`)
+    pub delta: NoiseStats,
+}
+
+fn div_round(a: i64, b: i64) -> i64 {
+    if (a < 0) == (b < 0) {
+        (a + b / 2) / b
+    } else {
+        (a - b / 2) / b
+    }
+}
+
+/// Integer square root, rounded to the nearest integer
+fn isqrt_round(value: u64) -> u64 {
+    // digit by digit, leaves the floor of the root and `value - root²` in `rem`
+    let mut rem = value;
+    let mut root = 0u64;
+    let mut bit = 1u64 << 62;
+    while bit > rem {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if rem >= root + bit {
+            rem -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+
+    if rem > root {
+        root + 1
+    } else {
+        root
+    }
+}
+
+#[test]
+fn metrics_from_sample() {
+    let sample = CountSample {
+        prox_filtered: 980,
+        prox_reference: 1000,
+        prox_threshold: 8,
+        touch_threshold: 32,
+        move_upper_reference: 520,
+        move_lower_reference: 500,
+        ..Default::default()
+    };
+
+    let metrics = Metrics::from(&sample);
+    assert_eq!(metrics.delta, 20);
+    assert_eq!(metrics.prox_margin, -12);
+    assert_eq!(metrics.touch_margin, 12);
+    assert_eq!(metrics.movement_window, 20);
+}
+
+#[test]
+fn noise_estimator_stats() {
+    let stats = [2, 4, 4, 4, 5, 5, 7, 9]
+        .into_iter()
+        .collect::<NoiseEstimator>()
+        .stats();
+    assert_eq!(stats.samples, 8);
+    assert_eq!(stats.mean, 5);
+    assert_eq!(stats.std_dev, 2);
+    assert_eq!(stats.peak_to_peak(), 7);
+
+    assert_eq!(NoiseEstimator::new().stats(), NoiseStats::default());
+}
+
+#[test]
+fn metrics_from_sim_counts() {
+    use crate::registers::Register;
+
+    let (sim, mut iqs) = crate::sim::test_device(None);
+    sim.set_count(Register::CH0_ACF_H, 985);
+    sim.set_count(Register::CH0_LTA_H, 1000);
+    sim.set_count(Register::CH1_UMOV_H, 530);
+    sim.set_count(Register::CH1_LMOV_H, 510);
+    let mut delay = sim.clone();
+
+    assert_eq!(iqs.get_proximity_delta().unwrap().value, 15);
+    assert_eq!(iqs.get_movement_window().unwrap().value, 20);
+    let metrics = iqs.read_metrics().unwrap().value;
+    assert_eq!(metrics.touch_margin, 32 - 15);
+
+    let noise = iqs.estimate_noise(&mut delay, 10, 5).unwrap();
+    assert_eq!(noise.delta.samples, 5);
+    assert_eq!(noise.delta.mean, 15);
+    assert_eq!(noise.delta.std_dev, 0);
+}
+
+#[test]
+fn isqrt_round_rounds_to_nearest() {
+    let roots = [0, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4];
+    for (value, root) in roots.into_iter().enumerate() {
+        assert_eq!(isqrt_round(value as u64), root, "sqrt({value})");
+    }
+    assert_eq!(isqrt_round(1_000_000), 1000);
+    assert_eq!(isqrt_round(u64::MAX), 1 << 32);
+}
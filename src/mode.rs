@@ -0,0 +1,63 @@
+//! Device modes, tracked in the type of the driver (`Iqs231<I, M>`).
+//!
+//! A new driver is `Streaming`: the device samples both channels and all registers can be read.
+//! The transitions consume the driver and return it in the new mode, so operations that make no
+//! sense there no longer compile:
+//!
+//! - `into_sensing_disabled()` and `into_ulp()` from `Streaming`, back with `into_streaming()`
+//! - `into_standalone()` from any mode, after which the device no longer talks I²C and the
+//!   `Standalone` driver only hands back the bus
+//!
+//! ```compile_fail
+//! use iqs231::{interface::I2cInterface, mode::SensingDisabled, Iqs231};
+//!
+//! fn counts<I: I2cInterface>(iqs: &mut Iqs231<I, SensingDisabled>) {
+//!     // count registers are only read while streaming
+//!     let _ = iqs.read_counts();
+//! }
+//! ```
+//!
+//! A device reset returns the device to streaming. In managed mode the driver enters its mode
+//! again along with restoring the configuration (see `Iqs231::enable_managed_mode()`). Otherwise
+//! the device streams while the driver is still typed for the previous mode, which only leaves
+//! fewer operations available than the device supports; `Iqs231::is_sensing_enabled()` and
+//! `ulp_mode()` report the actual state, and `into_streaming()` works either way.
+use crate::registers::Commands;
+
+/// Both channels sampled and streamed over I²C, the mode after a reset
+pub struct Streaming;
+
+/// Sensing disabled by `Commands::DISABLE_SENSING`, counts are not updated
+pub struct SensingDisabled;
+
+/// Ultra low power mode (`Commands::TOGGLE_ULP_MODE`), counts are not streamed
+pub struct UltraLowPower;
+
+/// Standalone mode (`Commands::STANDALONE`), the device no longer answers on I²C
+pub struct Standalone;
+
+/// Modes in which the device is accessed over I²C
+pub trait Mode: sealed::Sealed {
+    /// Commands entering the mode from `Streaming`
+    const COMMANDS: Commands;
+}
+
+impl Mode for Streaming {
+    const COMMANDS: Commands = Commands::empty();
+}
+
+impl Mode for SensingDisabled {
+    const COMMANDS: Commands = Commands::DISABLE_SENSING;
+}
+
+impl Mode for UltraLowPower {
+    const COMMANDS: Commands = Commands::TOGGLE_ULP_MODE;
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for super::Streaming {}
+    impl Sealed for super::SensingDisabled {}
+    impl Sealed for super::UltraLowPower {}
+}
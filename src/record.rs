@@ -0,0 +1,528 @@
+//! Recording and replaying of the I²C traffic between `Iqs231` and the device.
+//!
+//! Wrap the bus in a `Recorder` to capture the transactions into a caller-provided buffer, which
+//! keeps the most recent ones (also on `no_std` targets), and serve them back to the driver in a
+//! host test with `Replay`. With `std`, a log is stored with `write_log()` (one transaction per
+//! line) and loaded with `read_log()`. A log line looks like:
+//!
+//! ```text
+//! write_read 44 [15] -> [01 03 e8]
+//! write 44 [04 80]
+//! read 44 -> [01]
+//! ```
+//!
+//! A line ending in `!` marks a transaction that failed on the bus.
+use core::{fmt, ops::Deref};
+#[cfg(any(feature = "std", test))]
+use std::{
+    io::{self, BufRead},
+    str::FromStr,
+    string::String,
+    vec::Vec,
+};
+
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+
+use crate::{
+    interface::I2cInterface,
+    registers::{MainEvents, Register, REGISTER_COUNT},
+};
+
+/// Longest transfer recorded: a burst read of the whole register map after `MainEvents`.
+/// Longer transfers are truncated.
+pub const MAX_TRANSFER: usize = REGISTER_COUNT + 1;
+
+/// Bytes of a transfer, at most `MAX_TRANSFER`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Bytes {
+    len: u8,
+    bytes: [u8; MAX_TRANSFER],
+}
+
+impl Bytes {
+    pub const EMPTY: Self = Self {
+        len: 0,
+        bytes: [0; MAX_TRANSFER],
+    };
+
+    /// `bytes`, truncated to `MAX_TRANSFER`
+    pub fn new(bytes: &[u8]) -> Self {
+        let len = bytes.len().min(MAX_TRANSFER);
+        let mut new = Self::EMPTY;
+        new.bytes[..len].copy_from_slice(&bytes[..len]);
+        new.len = len as u8;
+        new
+    }
+
+    /// Append `byte`, `false` if already holding `MAX_TRANSFER` bytes
+    #[cfg(any(feature = "std", feature = "serde", test))]
+    pub(crate) fn push(&mut self, byte: u8) -> bool {
+        match self.bytes.get_mut(self.len as usize) {
+            Some(slot) => {
+                *slot = byte;
+                self.len += 1;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Default for Bytes {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl fmt::Debug for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02x?}", &**self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Kind {
+    Read,
+    Write,
+    WriteRead,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Transaction {
+    pub kind: Kind,
+    pub address: u8,
+    /// Bytes written (register address followed by the values)
+    pub written: Bytes,
+    /// Bytes read (`MainEvents` followed by the register values)
+    pub read: Bytes,
+    /// The transaction failed on the bus
+    pub failed: bool,
+}
+
+impl Transaction {
+    /// Placeholder to initialize a `Recorder` buffer with, e.g. `[Transaction::EMPTY; 64]`
+    pub const EMPTY: Self = Self {
+        kind: Kind::Read,
+        address: 0,
+        written: Bytes::EMPTY,
+        read: Bytes::EMPTY,
+        failed: false,
+    };
+
+    /// Register addressed by a write or write_read
+    pub fn register(&self) -> Option<Register> {
+        self.written
+            .first()
+            .and_then(|r| Register::try_from(*r).ok())
+    }
+
+    /// `MainEvents` returned by a read or write_read
+    pub fn main_events(&self) -> Option<MainEvents> {
+        self.read.first().map(|b| MainEvents::from_bits_retain(*b))
+    }
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            Kind::Read => "read",
+            Kind::Write => "write",
+            Kind::WriteRead => "write_read",
+        };
+        write!(f, "{} {:02x}", kind, self.address)?;
+
+        if self.kind != Kind::Read {
+            write!(f, " [{}]", Hex(&self.written))?;
+        }
+        if self.kind != Kind::Write {
+            write!(f, " -> [{}]", Hex(&self.read))?;
+        }
+        if self.failed {
+            write!(f, " !")?;
+        }
+        Ok(())
+    }
+}
+
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, b) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(feature = "std", test))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: String,
+}
+
+#[cfg(any(feature = "std", test))]
+impl FromStr for Transaction {
+    type Err = ParseError;
+
+    fn from_str(line: &str) -> Result<Self, ParseError> {
+        let err = || ParseError {
+            line: String::from(line),
+        };
+
+        let line_trimmed = line.trim();
+        let (body, failed) = match line_trimmed.strip_suffix('!') {
+            Some(body) => (body.trim_end(), true),
+            None => (line_trimmed, false),
+        };
+
+        let (head, rest) = body.split_once('[').map_or((body, ""), |(h, r)| (h, r));
+        let mut head = head.split_whitespace();
+        let kind = match head.next() {
+            Some("read") => Kind::Read,
+            Some("write") => Kind::Write,
+            Some("write_read") => Kind::WriteRead,
+            _ => return Err(err()),
+        };
+        let address = head
+            .next()
+            .and_then(|a| u8::from_str_radix(a, 16).ok())
+            .ok_or_else(err)?;
+
+        // remaining: "<written>]" and/or "-> [<read>]" (a read has no written part)
+        let rest = if kind == Kind::Read {
+            body.split_once("->").map(|(_, r)| r).ok_or_else(err)?
+        } else {
+            rest
+        };
+        let mut groups = rest
+            .split(['[', ']'])
+            .map(str::trim)
+            .filter(|g| !g.is_empty() && *g != "->");
+
+        let mut parse_group = || -> Result<Bytes, ParseError> {
+            let mut bytes = Bytes::EMPTY;
+            for b in groups.next().unwrap_or("").split_whitespace() {
+                let b = u8::from_str_radix(b, 16).map_err(|_| err())?;
+                if !bytes.push(b) {
+                    return Err(err());
+                }
+            }
+            Ok(bytes)
+        };
+
+        let (written, read) = match kind {
+            Kind::Read => (Bytes::EMPTY, parse_group()?),
+            Kind::Write => (parse_group()?, Bytes::EMPTY),
+            Kind::WriteRead => {
+                let written = parse_group()?;
+                (written, parse_group()?)
+            }
+        };
+
+        Ok(Self {
+            kind,
+            address,
+            written,
+            read,
+            failed,
+        })
+    }
+}
+
+/// Write a log, one transaction per line
+#[cfg(any(feature = "std", test))]
+pub fn write_log<'t, W: io::Write>(
+    log: impl IntoIterator<Item = &'t Transaction>,
+    mut out: W,
+) -> io::Result<()> {
+    for t in log {
+        writeln!(out, "{}", t)?;
+    }
+    Ok(())
+}
+
+/// Read a log as written by `write_log()`. Empty lines and lines starting with `#` are skipped.
+#[cfg(any(feature = "std", test))]
+pub fn read_log<R: BufRead>(input: R) -> io::Result<Vec<Transaction>> {
+    let mut log = Vec::new();
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let t = line
+            .parse()
+            .map_err(|e: ParseError| io::Error::new(io::ErrorKind::InvalidData, e.line))?;
+        log.push(t);
+    }
+    Ok(log)
+}
+
+/// Bus wrapper recording every transaction passed to the wrapped bus into `buffer`. When the
+/// buffer is full, the oldest transaction is overwritten.
+pub struct Recorder<'a, B> {
+    bus: B,
+    buffer: &'a mut [Transaction],
+    start: usize,
+    len: usize,
+}
+
+impl<'a, B> Recorder<'a, B> {
+    pub fn new(bus: B, buffer: &'a mut [Transaction]) -> Self {
+        Self {
+            bus,
+            buffer,
+            start: 0,
+            len: 0,
+        }
+    }
+
+    /// Recorded transactions, oldest first
+    pub fn log(&self) -> impl Iterator<Item = &Transaction> {
+        let (older, newer) = self.buffer.split_at(self.start);
+        newer.iter().chain(older).take(self.len)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+
+    pub fn into_inner(self) -> B {
+        self.bus
+    }
+
+    fn record<E>(
+        &mut self,
+        kind: Kind,
+        address: u8,
+        written: &[u8],
+        read: &[u8],
+        result: Result<(), E>,
+    ) -> Result<(), E> {
+        let capacity = self.buffer.len();
+        if capacity == 0 {
+            return result;
+        }
+
+        let transaction = Transaction {
+            kind,
+            address,
+            written: Bytes::new(written),
+            read: Bytes::new(read),
+            failed: result.is_err(),
+        };
+        if self.len < capacity {
+            self.buffer[(self.start + self.len) % capacity] = transaction;
+            self.len += 1;
+        } else {
+            self.buffer[self.start] = transaction;
+            self.start = (self.start + 1) % capacity;
+        }
+        result
+    }
+}
+
+impl<B: I2cInterface> I2cInterface for Recorder<'_, B> {
+    type Error = B::Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        let result = self.bus.read(address, buffer);
+        self.record(Kind::Read, address, &[], buffer, result)
+    }
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        let result = self.bus.write(address, bytes);
+        self.record(Kind::Write, address, bytes, &[], result)
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        let result = self.bus.write_read(address, bytes, buffer);
+        self.record(Kind::WriteRead, address, bytes, buffer, result)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// All recorded transactions have been served
+    Exhausted,
+    /// The driver issued a different transaction than the one recorded at `index`
+    Mismatch { index: usize, expected: Transaction },
+    /// The recorded transaction failed on the bus
+    Recorded,
+}
+
+/// Bus serving a recorded log back to the driver. Each transaction must match the recorded one
+/// (kind, address, written bytes and read length).
+pub struct Replay<'a> {
+    log: &'a [Transaction],
+    pos: usize,
+}
+
+impl<'a> Replay<'a> {
+    pub fn new(log: &'a [Transaction]) -> Self {
+        Self { log, pos: 0 }
+    }
+
+    /// All recorded transactions have been served
+    pub fn is_finished(&self) -> bool {
+        self.pos == self.log.len()
+    }
+
+    /// Number of transactions served so far
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn serve(
+        &mut self,
+        kind: Kind,
+        address: u8,
+        written: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), ReplayError> {
+        let index = self.pos;
+        let expected = self.log.get(index).ok_or(ReplayError::Exhausted)?;
+
+        if expected.kind != kind
+            || expected.address != address
+            || *expected.written != *written
+            || (!expected.failed && expected.read.len() != buffer.len())
+        {
+            return Err(ReplayError::Mismatch {
+                index,
+                expected: *expected,
+            });
+        }
+
+        self.pos += 1;
+        if expected.failed {
+            Err(ReplayError::Recorded)
+        } else {
+            buffer.copy_from_slice(&expected.read);
+            Ok(())
+        }
+    }
+}
+
+impl Read for Replay<'_> {
+    type Error = ReplayError;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), ReplayError> {
+        self.serve(Kind::Read, address, &[], buffer)
+    }
+}
+
+impl Write for Replay<'_> {
+    type Error = ReplayError;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), ReplayError> {
+        self.serve(Kind::Write, address, bytes, &mut [])
+    }
+}
+
+impl WriteRead for Replay<'_> {
+    type Error = ReplayError;
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), ReplayError> {
+        self.serve(Kind::WriteRead, address, bytes, buffer)
+    }
+}
+
+#[test]
+fn transaction_text_roundtrip() {
+    let lines = [
+        "write_read 44 [15] -> [01 03 e8]",
+        "write 44 [04 80]",
+        "read 44 -> [08]",
+        "write 45 [00] !",
+    ];
+    for line in lines {
+        let t: Transaction = line.parse().unwrap();
+        assert_eq!(std::format!("{}", t), line);
+    }
+
+    let t: Transaction = lines[0].parse().unwrap();
+    assert_eq!(t.register(), Some(Register::CH0_ACF_H));
+    assert_eq!(t.main_events(), Some(MainEvents::PROX));
+    assert!("bogus 44".parse::<Transaction>().is_err());
+}
+
+#[test]
+fn recorded_session_replays() {
+    use crate::{sim::SimIqs231, Iqs231};
+
+    let sim = SimIqs231::new();
+    sim.set_count(Register::CH0_LTA_H, 812);
+    let mut buffer = [Transaction::EMPTY; 8];
+    let mut iqs = Iqs231::new(Recorder::new(sim, &mut buffer));
+    iqs.set_ch0_compensation(99).unwrap();
+    let lta = iqs.get_prox_reference_count().unwrap();
+    let comp = iqs.get_ch0_compensation().unwrap();
+
+    let mut text = Vec::new();
+    write_log(iqs.destroy().log(), &mut text).unwrap();
+    let log = read_log(text.as_slice()).unwrap();
+    assert_eq!(log.len(), 3);
+
+    let mut iqs = Iqs231::new(Replay::new(&log));
+    iqs.set_ch0_compensation(99).unwrap();
+    assert_eq!(iqs.get_prox_reference_count().unwrap(), lta);
+    assert_eq!(iqs.get_ch0_compensation().unwrap(), comp);
+    assert!(iqs.destroy().is_finished());
+}
+
+#[test]
+fn recorder_keeps_the_latest_transactions() {
+    use crate::sim::SimIqs231;
+
+    let mut buffer = [Transaction::EMPTY; 2];
+    let mut recorder = Recorder::new(SimIqs231::new(), &mut buffer);
+    let registers = [
+        Register::TouchThreshold,
+        Register::CH0_Compensation,
+        Register::CH1_Compensation,
+    ];
+    for register in registers {
+        recorder.write(0x44, &[register as u8, 0]).unwrap();
+    }
+
+    assert_eq!(recorder.len(), 2);
+    assert!(recorder.log().map(Transaction::register).eq([
+        Some(Register::CH0_Compensation),
+        Some(Register::CH1_Compensation)
+    ]));
+}
@@ -1,13 +1,18 @@
 // For the i2c register reference, see datasheet: https://www.azoteq.com/images/stories/pdf/iqs231a_datasheet.pdf (pg. 14 and pg. 30 onwards)
 use core::ops::Deref;
-use modular_bitfield::prelude::*;
-use num_enum::{FromPrimitive, IntoPrimitive, TryFromPrimitive};
+use modular_bitfield::{
+    error::{InvalidBitPattern, OutOfBounds},
+    prelude::*,
+};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
 
-use crate::Error;
+use crate::{device::I2cAddress, Error};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 /// Any read from the iqs231 includes (is prepended) by the `MainEvents` byte.
 /// The read functions return this struct wrapping `MainEvents` with the actual (register) value
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct RegValue<T> {
     pub main_events: MainEvents,
     pub value: T,
@@ -58,6 +63,19 @@ impl From<RegValue<u8>> for u8 {
     }
 }
 
+impl<const N: usize> RegValue<[u8; N]> {
+    /// Build from a burst read buffer: the leading `MainEvents` byte followed by `N` register values
+    pub(crate) fn from_burst(bytes: &[u8]) -> Self {
+        let mut value = [0u8; N];
+        value.copy_from_slice(&bytes[1..=N]);
+
+        Self {
+            main_events: MainEvents::from_bits_retain(bytes[0]),
+            value,
+        }
+    }
+}
+
 impl From<[u8; 2]> for RegValue<u8> {
     fn from(bytes: [u8; 2]) -> Self {
         Self {
@@ -70,6 +88,8 @@ impl From<[u8; 2]> for RegValue<u8> {
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, TryFromPrimitive)]
 #[allow(dead_code, non_camel_case_types)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Register {
     ProductNumber = 0x00,   // R 0x40 0x40
     SoftwareVersion = 0x01, // R 0x06 0x06 (IQS231A), 0x07 (IQS231B – Identical to 0x06 software)
@@ -139,10 +159,6 @@ impl Register {
         )
     }
 
-    pub(crate) fn next<T>(self) -> Result<Self, Error<T>> {
-        Self::from_u8(self as u8 + 1)
-    }
-
     pub(crate) fn from_u8<T>(reg_nr: u8) -> Result<Self, Error<T>> {
         Self::try_from_primitive(reg_nr).map_err(|_| Error::InvalidRegister)
     }
@@ -154,10 +170,15 @@ impl From<Register> for u8 {
     }
 }
 
+/// Number of registers in the register map (`ProductNumber` up to and including `TimerRedoAti`)
+pub const REGISTER_COUNT: usize = Register::TimerRedoAti as usize + 1;
+
 pub const PRODUCT_NUMBER: u8 = 0x40;
 
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum SoftwareVersion {
     IQS231A = 0x06,
     IQS231B = 0x07,
@@ -165,6 +186,11 @@ pub enum SoftwareVersion {
 
 bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(transparent)
+    )]
     pub struct Commands: u8 {
         const ATI_CH0 = 0x80;
         const DISABLE_SENSING = 0x40;
@@ -179,6 +205,11 @@ bitflags::bitflags! {
 
 bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(transparent)
+    )]
     pub struct MainEvents: u8 {
         const SENSING_DISABLED = 0x20;
         const WARM_BOOT = 0x10;
@@ -191,6 +222,11 @@ bitflags::bitflags! {
 
 bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(transparent)
+    )]
     pub struct DebugEvents: u8 {
         const _RESERVED1 = 0x80;
         const ATI_ERROR = 0x40;
@@ -205,6 +241,11 @@ bitflags::bitflags! {
 
 bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(transparent)
+    )]
     pub struct SystemFlags: u8 {
         const I2C  = 0x80;
         const TEMP = 0x40;
@@ -219,6 +260,11 @@ bitflags::bitflags! {
 
 bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(transparent)
+    )]
     pub struct UiFlags: u8 {
         const TEMP_CHANNEL_ATI = 0x80;
         const TEMPERATURE_RESEED = 0x40;
@@ -232,7 +278,12 @@ bitflags::bitflags! {
 }
 
 bitflags::bitflags! {
-    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(transparent)
+    )]
     pub struct EventFlags: u8 {
         const CH1_ATI_ERROR = 0x80;
         const _RESERVED2 = 0x40;
@@ -245,18 +296,175 @@ bitflags::bitflags! {
     }
 }
 
-#[bitfield(bits = 8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct OtpBank1 {
-    pub touch_thresh: B2,
-    pub ac_filter: B2,
-    pub prox_thresh: ProximityThreshold,
-    pub i2c_addr: B2,
+bitflags::bitflags! {
+    /// ATI (auto tuning) status per channel. Use together with `DebugEvents::ATI_ERROR` and the
+    /// `EventFlags` ATI error bits to find out why tuning failed.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(
+        feature = "serde",
+        derive(serde::Serialize, serde::Deserialize),
+        serde(transparent)
+    )]
+    pub struct AtiFlags: u8 {
+        const _RESERVED1 = 0x80;
+        const CH1_ATI_BUSY = 0x40;
+        /// Compensation saturated at 255, target count could not be reached (too little signal)
+        const CH1_COMPENSATION_MAX = 0x20;
+        /// Compensation saturated at 0, target count could not be reached (too much signal)
+        const CH1_COMPENSATION_MIN = 0x10;
+        const _RESERVED2 = 0x08;
+        const CH0_ATI_BUSY = 0x04;
+        /// Compensation saturated at 255, target count could not be reached (too little signal)
+        const CH0_COMPENSATION_MAX = 0x02;
+        /// Compensation saturated at 0, target count could not be reached (too much signal)
+        const CH0_COMPENSATION_MIN = 0x01;
+    }
+}
+
+/// The `Debug` impl generated by `#[bitfield]` trips `unused_parens` and can not carry an `allow`
+/// itself, so the register bitfields are declared in their own module
+#[allow(unused_parens)]
+mod bitfields {
+    use super::*;
+
+    #[bitfield(bits = 8)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct OtpBank1 {
+        pub touch_thresh: OtpTouchThreshold,
+        pub ac_filter: AcFilter,
+        pub prox_thresh: ProximityThreshold,
+        pub i2c_addr: I2cAddress,
+    }
+
+    #[bitfield(bits = 8)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct OtpBank2 {
+        pub ui_select: UiSelect,
+        /// Quick release enabled
+        pub quick_release: bool,
+        pub failsafe_pulses_on_io1: bool,
+        pub base_value: BaseValue,
+        pub target: AtiTarget,
+        pub increase_debounce: bool,
+    }
+
+    #[bitfield(bits = 8)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct OtpBank3 {
+        pub sample_rate: SampleRate,
+        pub ati_events_on_io1: bool,
+
+        pub io2_function: Io2Function,
+        pub temp_n_interference_compensation: bool,
+        pub charge_transfer_freq: ChargeTransferFrequency,
+    }
+
+    #[bitfield(bits = 8)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct QuickRelease {
+        pub beta: B4,
+        pub threshold: QuickReleaseThreshold,
+    }
+
+    /// Movement channel settings (register 0x09, default 0x34)
+    #[bitfield(bits = 8)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Movement {
+        /// Movement threshold in counts (1..=15)
+        pub threshold: B4,
+        pub debounce: MovementDebounce,
+        /// Time without movement after which the proximity LTA halt times out
+        pub timeout: MovementTimeout,
+    }
+
+    #[bitfield(bits = 8)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ChannelMultiplier {
+        pub compensation_multiplier: B4,
+        pub sensitivity_multiplier: B2,
+        #[skip]
+        __: B2,
+    }
+}
+
+pub use bitfields::{ChannelMultiplier, Movement, OtpBank1, OtpBank2, OtpBank3, QuickRelease};
+
+impl Default for OtpBank1 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for OtpBank2 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for OtpBank3 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for QuickRelease {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for Movement {
+    fn default() -> Self {
+        Self::from_bytes([0x34])
+    }
+}
+
+impl Default for ChannelMultiplier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Touch threshold selected in OTP (the `TouchThreshold` register overrides it at runtime)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
+#[bits = 2]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OtpTouchThreshold {
+    Counts32, //0x0
+    Counts16, //0x1
+    Counts48, //0x2
+    Counts64, //0x3
+}
+
+impl OtpTouchThreshold {
+    pub fn counts(&self) -> u16 {
+        match self {
+            Self::Counts32 => 32,
+            Self::Counts16 => 16,
+            Self::Counts48 => 48,
+            Self::Counts64 => 64,
+        }
+    }
+}
+
+/// AC filter strength, `Commands::TOGGLE_AC_FILTER` switches the filter at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
+#[bits = 2]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AcFilter {
+    Normal, //0x0
+    Light,  //0x1
+    Heavy,  //0x2
+    Off,    //0x3
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier, IntoPrimitive)]
 #[repr(u8)]
 #[bits = 2]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ProximityThreshold {
     Counts4,  //0x0
     Counts6,  //0x1
@@ -264,6 +472,17 @@ pub enum ProximityThreshold {
     Counts10, //0x3
 }
 
+impl ProximityThreshold {
+    pub fn counts(&self) -> u16 {
+        match self {
+            Self::Counts4 => 4,
+            Self::Counts6 => 6,
+            Self::Counts8 => 8,
+            Self::Counts10 => 10,
+        }
+    }
+}
+
 impl From<u8> for ProximityThreshold {
     fn from(value: u8) -> Self {
         match value & 0x03 {
@@ -276,19 +495,28 @@ impl From<u8> for ProximityThreshold {
     }
 }
 
-#[bitfield(bits = 8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct OtpBank2 {
-    pub ui_select: UiSelect,
-    pub quick_release: B1,
-    pub failsafe_pulses_on_io1: bool,
-    pub base_value: BaseValue,
-    pub target: B1,
-    pub increase_debounce: bool,
+/// Encoded in `OtpBank1::i2c_addr` as the offset from `I2cAddress::Default`
+impl Specifier for I2cAddress {
+    const BITS: usize = 2;
+    type Bytes = u8;
+    type InOut = Self;
+
+    fn into_bytes(input: Self) -> Result<u8, OutOfBounds> {
+        Ok(input as u8 - Self::Default as u8)
+    }
+
+    fn from_bytes(bytes: u8) -> Result<Self, InvalidBitPattern<u8>> {
+        Self::ALL
+            .get(bytes as usize)
+            .copied()
+            .ok_or(InvalidBitPattern::new(bytes))
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 2]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum UiSelect {
     ProxNoMov,             //0x0
     ProxWithMov,           //0x1
@@ -298,6 +526,8 @@ pub enum UiSelect {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 2]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum BaseValue {
     Counts100, //0x0
     Counts75,  //0x1
@@ -305,19 +535,40 @@ pub enum BaseValue {
     Counts200, //0x3
 }
 
-#[bitfield(bits = 8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct OtpBank3 {
-    pub sample_rate: SampleRate,
-    pub ati_events_on_io1: B1,
+impl BaseValue {
+    pub fn counts(&self) -> u16 {
+        match self {
+            Self::Counts100 => 100,
+            Self::Counts75 => 75,
+            Self::Counts150 => 150,
+            Self::Counts200 => 200,
+        }
+    }
+}
+
+/// Count the ATI compensation tunes the proximity channel to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
+#[bits = 1]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AtiTarget {
+    Counts1024, //0x0
+    Counts512,  //0x1
+}
 
-    pub io2_function: Io2Function,
-    pub temp_n_interference_compensation: bool,
-    pub charge_transfer_freq: ChargeTransferFrequency,
+impl AtiTarget {
+    pub fn counts(&self) -> u16 {
+        match self {
+            Self::Counts1024 => 1024,
+            Self::Counts512 => 512,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 2]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum SampleRate {
     _30Hz,  // 0x0 (57ms)
     _100Hz, // 0x1 (34ms)
@@ -325,8 +576,22 @@ pub enum SampleRate {
     _4Hz,   // 0x3 (280ms)
 }
 
+impl SampleRate {
+    /// Sample period in milliseconds
+    pub fn period_ms(&self) -> u16 {
+        match self {
+            Self::_30Hz => 57,
+            Self::_100Hz => 34,
+            Self::_8Hz => 154,
+            Self::_4Hz => 280,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 2]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Io2Function {
     Sensitivity, // 0x00 – Sensitivity input    (proximity threshold adjust)
     Synchronize, // 0x01 – Synchronize input
@@ -336,6 +601,8 @@ pub enum Io2Function {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 2]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ChargeTransferFrequency {
     _500kHz, // 0x00 – 500kHz
     _125kHz, // 0x01 – 125 kHz
@@ -343,15 +610,10 @@ pub enum ChargeTransferFrequency {
     _16kHz,  // 0x11 – 16.5kHz
 }
 
-#[bitfield(bits = 8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct QuickRelease {
-    pub beta: B4,
-    pub threshold: QuickReleaseThreshold,
-}
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
 #[bits = 4]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum QuickReleaseThreshold {
     Qrt100,  //0x0
     Qrt150,  //0x1
@@ -394,12 +656,48 @@ impl QuickReleaseThreshold {
     }
 }
 
-#[bitfield(bits = 8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct ChannelMultiplier {
-    pub compensation_multiplier: B4,
-    pub sensitivity_multiplier: B2,
-    reserved: B2,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
+#[bits = 2]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MovementDebounce {
+    Samples2, //0x0
+    Samples4, //0x1
+    Samples6, //0x2
+    Samples8, //0x3
+}
+
+impl MovementDebounce {
+    pub fn samples(&self) -> u8 {
+        match self {
+            Self::Samples2 => 2,
+            Self::Samples4 => 4,
+            Self::Samples6 => 6,
+            Self::Samples8 => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BitfieldSpecifier)]
+#[bits = 2]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MovementTimeout {
+    Minutes5,  //0x0
+    Minutes10, //0x1
+    Minutes30, //0x2
+    Minutes90, //0x3
+}
+
+impl MovementTimeout {
+    pub fn minutes(&self) -> u8 {
+        match self {
+            Self::Minutes5 => 5,
+            Self::Minutes10 => 10,
+            Self::Minutes30 => 30,
+            Self::Minutes90 => 90,
+        }
+    }
 }
 
 #[test]
@@ -413,12 +711,28 @@ fn otpbank3_bitfield_does_its_thing() {
     let otp = OtpBank3::new()
         .with_charge_transfer_freq(ChargeTransferFrequency::_125kHz)
         .with_sample_rate(SampleRate::_30Hz)
-        .with_ati_events_on_io1(1)
+        .with_ati_events_on_io1(true)
         .with_temp_n_interference_compensation(true);
 
     assert_eq!(otp.into_bytes()[0], 0x64)
 }
 
+#[test]
+fn otpbank1_and_2_fields_are_typed() {
+    let otp = OtpBank1::from_bytes([0x9e]);
+    assert_eq!(otp.i2c_addr(), I2cAddress::Alt1);
+    assert_eq!(otp.prox_thresh(), ProximityThreshold::Counts6);
+    assert_eq!(otp.ac_filter(), AcFilter::Off);
+    assert_eq!(otp.touch_thresh(), OtpTouchThreshold::Counts48);
+    assert_eq!(otp.touch_thresh().counts(), 48);
+
+    let otp = OtpBank2::new()
+        .with_quick_release(true)
+        .with_target(AtiTarget::Counts512);
+    assert_eq!(otp.into_bytes()[0], 0x44);
+    assert_eq!(otp.base_value().counts(), 100);
+}
+
 #[test]
 fn quickrelease_bitfield_does_its_thing() {
     let qr = QuickRelease::from_bytes([0xb4]);
@@ -427,12 +741,33 @@ fn quickrelease_bitfield_does_its_thing() {
     assert_eq!(qr.threshold().counts(), 400);
 
     let qrr = QuickRelease::new()
-        .with_base(5)
+        .with_beta(5)
         .with_threshold(QuickReleaseThreshold::Qrt200);
-    assert_eq!(qrr.bytes, [0x95]);
+    assert_eq!(qrr.into_bytes(), [0x95]);
 
     let qr2 = QuickRelease::from_bytes([0x4a]);
     assert_eq!(qr2.beta(), 0xa);
     assert_eq!(qr2.threshold(), QuickReleaseThreshold::Qrt10);
     assert_eq!(qr2.threshold().counts(), 10);
 }
+
+#[test]
+fn regvalue_from_burst_splits_main_events() {
+    let rv = RegValue::<[u8; 2]>::from_burst(&[0x0a, 0x03, 0xe8]);
+    assert_eq!(rv.main_events, MainEvents::COLD_BOOT | MainEvents::TOUCH);
+    assert_eq!(rv.map(u16::from_be_bytes).value, 1000);
+}
+
+#[test]
+fn movement_bitfield_does_its_thing() {
+    let mov = Movement::default();
+    assert_eq!(mov.threshold(), 4);
+    assert_eq!(mov.debounce(), MovementDebounce::Samples8);
+    assert_eq!(mov.timeout(), MovementTimeout::Minutes5);
+
+    let mov = Movement::new()
+        .with_threshold(10)
+        .with_debounce(MovementDebounce::Samples4)
+        .with_timeout(MovementTimeout::Minutes90);
+    assert_eq!(mov.into_bytes(), [0xda]);
+}
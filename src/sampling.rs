@@ -0,0 +1,276 @@
+//! Periodic sampling of the channel counts for tuning, see `Iqs231::sample_counts()`.
+//!
+//! Each `CountSample` is read in a single burst (`TouchThreshold` up to `Temperature_L`), so the
+//! counts and the thresholds they are compared against always belong to the same sample.
+//! With `std` the samples can be exported as CSV or JSON lines for plotting.
+use crate::{
+    device::touch_threshold_from_reg,
+    metrics::proximity_delta,
+    registers::{EventFlags, ProximityThreshold, Register},
+};
+
+/// First register of the `CountSample` burst
+pub(crate) const SAMPLE_FIRST_REGISTER: Register = Register::TouchThreshold;
+/// Number of registers in the `CountSample` burst (`TouchThreshold` up to `Temperature_L`)
+pub(crate) const SAMPLE_REGISTER_COUNT: usize =
+    Register::Temperature_L as usize - SAMPLE_FIRST_REGISTER as usize + 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CountSample {
+    /// Time since the first sample in the buffer (nominal, sample index × period), wrapping
+    /// around after `u32::MAX` ms
+    pub time_ms: u32,
+    pub event_flags: EventFlags,
+
+    /// Proximity channel: Filtered count value (ACF)
+    pub prox_filtered: u16,
+    /// Proximity channel: Reference count value (LTA)
+    pub prox_reference: u16,
+    /// Movement channel: Filtered count value
+    pub move_filtered: u16,
+    /// Movement channel: Upper reference count value (UMOV)
+    pub move_upper_reference: u16,
+    /// Movement channel: Lower reference count value (LMOV)
+    pub move_lower_reference: u16,
+    /// Movement channel temperature reference
+    pub temp_reference: u16,
+
+    /// Proximity threshold in counts
+    pub prox_threshold: u16,
+    /// Touch threshold in counts
+    pub touch_threshold: u16,
+}
+
+impl CountSample {
+    pub(crate) fn from_registers(regs: &[u8; SAMPLE_REGISTER_COUNT]) -> Self {
+        let at = |r: Register| r as usize - SAMPLE_FIRST_REGISTER as usize;
+        let reg = |r: Register| regs[at(r)];
+        let reg16 = |r: Register| u16::from_be_bytes([regs[at(r)], regs[at(r) + 1]]);
+
+        Self {
+            time_ms: 0,
+            event_flags: EventFlags::from_bits_retain(reg(Register::EventFlags)),
+            prox_filtered: reg16(Register::CH0_ACF_H),
+            prox_reference: reg16(Register::CH0_LTA_H),
+            move_filtered: reg16(Register::CH1_ACF_H),
+            move_upper_reference: reg16(Register::CH1_UMOV_H),
+            move_lower_reference: reg16(Register::CH1_LMOV_H),
+            temp_reference: reg16(Register::Temperature_H),
+            prox_threshold: ProximityThreshold::from(reg(Register::ProximityThreshold)).counts(),
+            touch_threshold: touch_threshold_from_reg(reg(Register::TouchThreshold)),
+        }
+    }
+
+    /// Proximity delta (LTA − ACF), the value compared against the proximity and touch
+    /// thresholds. Positive when an object approaches the electrode.
+    pub fn delta(&self) -> i32 {
+        proximity_delta(self.prox_reference, self.prox_filtered)
+    }
+}
+
+/// Fixed size ring buffer of samples, the oldest sample is dropped when full
+#[derive(Debug, Clone)]
+pub struct SampleBuffer<const N: usize> {
+    samples: [CountSample; N],
+    start: usize,
+    len: usize,
+}
+
+impl<const N: usize> Default for SampleBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> SampleBuffer<N> {
+    pub fn new() -> Self {
+        Self {
+            samples: [CountSample::default(); N],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, sample: CountSample) {
+        if N == 0 {
+            return;
+        }
+        if self.len < N {
+            self.samples[(self.start + self.len) % N] = sample;
+            self.len += 1;
+        } else {
+            self.samples[self.start] = sample;
+            self.start = (self.start + 1) % N;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+
+    /// Most recent sample
+    pub fn latest(&self) -> Option<&CountSample> {
+        self.len
+            .checked_sub(1)
+            .map(|last| &self.samples[(self.start + last) % N])
+    }
+
+    /// Iterate from the oldest to the most recent sample
+    pub fn iter(&self) -> impl Iterator<Item = &CountSample> {
+        (0..self.len).map(move |i| &self.samples[(self.start + i) % N])
+    }
+}
+
+#[cfg(any(feature = "std", test))]
+pub use export::{write_csv, write_csv_row, write_json_lines, CSV_HEADER};
+
+#[cfg(any(feature = "std", test))]
+mod export {
+    use std::io;
+
+    use super::CountSample;
+
+    pub const CSV_HEADER: &str = "time_ms,prox_filtered,prox_reference,delta,prox_threshold,\
+        touch_threshold,move_filtered,move_upper_reference,move_lower_reference,temp_reference,\
+        event_flags";
+
+    /// Write a single sample as CSV row (see `CSV_HEADER` for the columns)
+    pub fn write_csv_row<W: io::Write>(s: &CountSample, out: &mut W) -> io::Result<()> {
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{},{:#04x}",
+            s.time_ms,
+            s.prox_filtered,
+            s.prox_reference,
+            s.delta(),
+            s.prox_threshold,
+            s.touch_threshold,
+            s.move_filtered,
+            s.move_upper_reference,
+            s.move_lower_reference,
+            s.temp_reference,
+            s.event_flags.bits()
+        )
+    }
+
+    /// Write the samples as CSV, including the header line
+    pub fn write_csv<'a, W: io::Write>(
+        samples: impl IntoIterator<Item = &'a CountSample>,
+        mut out: W,
+    ) -> io::Result<()> {
+        writeln!(out, "{}", CSV_HEADER)?;
+        for s in samples {
+            write_csv_row(s, &mut out)?;
+        }
+        Ok(())
+    }
+
+    /// Write the samples as JSON lines, one object per sample
+    pub fn write_json_lines<'a, W: io::Write>(
+        samples: impl IntoIterator<Item = &'a CountSample>,
+        mut out: W,
+    ) -> io::Result<()> {
+        for s in samples {
+            writeln!(
+                out,
+                "{{\"time_ms\":{},\"prox_filtered\":{},\"prox_reference\":{},\"delta\":{},\
+                 \"prox_threshold\":{},\"touch_threshold\":{},\"move_filtered\":{},\
+                 \"move_upper_reference\":{},\"move_lower_reference\":{},\"temp_reference\":{},\
+                 \"event_flags\":{}}}",
+                s.time_ms,
+                s.prox_filtered,
+                s.prox_reference,
+                s.delta(),
+                s.prox_threshold,
+                s.touch_threshold,
+                s.move_filtered,
+                s.move_upper_reference,
+                s.move_lower_reference,
+                s.temp_reference,
+                s.event_flags.bits()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn sample_buffer_keeps_most_recent() {
+    let mut buffer = SampleBuffer::<3>::new();
+    assert!(buffer.latest().is_none());
+
+    for t in 0..5 {
+        buffer.push(CountSample {
+            time_ms: t,
+            ..Default::default()
+        });
+    }
+    assert!(buffer.is_full());
+    assert!(buffer.iter().map(|s| s.time_ms).eq([2, 3, 4]));
+    assert_eq!(buffer.latest().unwrap().time_ms, 4);
+}
+
+#[test]
+fn samples_export_as_csv_and_json() {
+    let sample = CountSample {
+        time_ms: 100,
+        prox_filtered: 990,
+        prox_reference: 1000,
+        prox_threshold: 4,
+        touch_threshold: 32,
+        event_flags: EventFlags::CH0_PROX,
+        ..Default::default()
+    };
+
+    let mut csv = std::vec::Vec::new();
+    write_csv([&sample], &mut csv).unwrap();
+    let csv = std::string::String::from_utf8(csv).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some(CSV_HEADER));
+    assert_eq!(lines.next(), Some("100,990,1000,10,4,32,0,0,0,0,0x01"));
+
+    let mut json = std::vec::Vec::new();
+    write_json_lines([&sample], &mut json).unwrap();
+    let json = std::string::String::from_utf8(json).unwrap();
+    assert!(json.starts_with(
+        "{\"time_ms\":100,\"prox_filtered\":990,\"prox_reference\":1000,\"delta\":10,"
+    ));
+    assert!(json.ends_with("\"event_flags\":1}\n"));
+}
+
+#[test]
+fn sample_counts_follow_the_model() {
+    use crate::{registers::ProximityThreshold, sim::SensorModel};
+
+    let (sim, mut iqs) = crate::sim::test_device(Some(SensorModel::new(1000)));
+    let mut delay = sim.clone();
+    iqs.set_proximity_threshold(ProximityThreshold::Counts8)
+        .unwrap();
+
+    let mut buffer = SampleBuffer::<4>::new();
+    iqs.sample_counts(&mut buffer, &mut delay, 100, 2).unwrap();
+    sim.set_signal(50);
+    iqs.sample_counts(&mut buffer, &mut delay, 100, 3).unwrap();
+
+    assert!(buffer.is_full());
+    assert!(buffer.iter().map(|s| s.time_ms).eq([100, 200, 300, 400]));
+    let latest = buffer.latest().unwrap();
+    assert_eq!(latest.prox_threshold, 8);
+    assert!(latest.delta() >= 8);
+    assert!(latest.event_flags.contains(EventFlags::CH0_PROX));
+}
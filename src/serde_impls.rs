@@ -0,0 +1,172 @@
+//! `serde` support for the bitfield registers (`serde` feature).
+//!
+//! Human readable formats (JSON, TOML, ...) get a struct with the named fields, binary formats
+//! (postcard, bincode, ...) the raw register byte.
+use serde::{
+    de::{Error as _, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+    device::I2cAddress,
+    record,
+    registers::{
+        self, AcFilter, AtiTarget, BaseValue, ChargeTransferFrequency, Io2Function,
+        MovementDebounce, MovementTimeout, OtpTouchThreshold, ProximityThreshold,
+        QuickReleaseThreshold, SampleRate, UiSelect,
+    },
+};
+
+macro_rules! bitfield_serde {
+    ($($name:ident { $($field:ident: $ty:ty => $setter:ident),* $(,)? })*) => {$(
+        const _: () = {
+            /// Named fields, for human readable formats
+            #[derive(Serialize, Deserialize)]
+            #[serde(deny_unknown_fields)]
+            struct $name {
+                $($field: $ty,)*
+            }
+
+            impl Serialize for registers::$name {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    if serializer.is_human_readable() {
+                        $name { $($field: self.$field(),)* }.serialize(serializer)
+                    } else {
+                        serializer.serialize_u8(self.into_bytes()[0])
+                    }
+                }
+            }
+
+            impl<'de> Deserialize<'de> for registers::$name {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    if deserializer.is_human_readable() {
+                        let fields = $name::deserialize(deserializer)?;
+                        let mut value = Self::new();
+                        $(
+                            value.$setter(fields.$field).map_err(|_| {
+                                D::Error::custom(concat!("`", stringify!($field), "` out of range"))
+                            })?;
+                        )*
+                        Ok(value)
+                    } else {
+                        Ok(Self::from_bytes([u8::deserialize(deserializer)?]))
+                    }
+                }
+            }
+        };
+    )*};
+}
+
+bitfield_serde! {
+    OtpBank1 {
+        touch_thresh: OtpTouchThreshold => set_touch_thresh_checked,
+        ac_filter: AcFilter => set_ac_filter_checked,
+        prox_thresh: ProximityThreshold => set_prox_thresh_checked,
+        i2c_addr: I2cAddress => set_i2c_addr_checked,
+    }
+    OtpBank2 {
+        ui_select: UiSelect => set_ui_select_checked,
+        quick_release: bool => set_quick_release_checked,
+        failsafe_pulses_on_io1: bool => set_failsafe_pulses_on_io1_checked,
+        base_value: BaseValue => set_base_value_checked,
+        target: AtiTarget => set_target_checked,
+        increase_debounce: bool => set_increase_debounce_checked,
+    }
+    OtpBank3 {
+        sample_rate: SampleRate => set_sample_rate_checked,
+        ati_events_on_io1: bool => set_ati_events_on_io1_checked,
+        io2_function: Io2Function => set_io2_function_checked,
+        temp_n_interference_compensation: bool => set_temp_n_interference_compensation_checked,
+        charge_transfer_freq: ChargeTransferFrequency => set_charge_transfer_freq_checked,
+    }
+    QuickRelease {
+        beta: u8 => set_beta_checked,
+        threshold: QuickReleaseThreshold => set_threshold_checked,
+    }
+    Movement {
+        threshold: u8 => set_threshold_checked,
+        debounce: MovementDebounce => set_debounce_checked,
+        timeout: MovementTimeout => set_timeout_checked,
+    }
+    ChannelMultiplier {
+        compensation_multiplier: u8 => set_compensation_multiplier_checked,
+        sensitivity_multiplier: u8 => set_sensitivity_multiplier_checked,
+    }
+}
+
+impl Serialize for record::Bytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for record::Bytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BytesVisitor;
+
+        impl<'de> Visitor<'de> for BytesVisitor {
+            type Value = record::Bytes;
+
+            fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                write!(f, "at most {} bytes", record::MAX_TRANSFER)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut bytes = record::Bytes::EMPTY;
+                while let Some(byte) = seq.next_element()? {
+                    if !bytes.push(byte) {
+                        return Err(A::Error::invalid_length(bytes.len() + 1, &self));
+                    }
+                }
+                Ok(bytes)
+            }
+        }
+
+        deserializer.deserialize_seq(BytesVisitor)
+    }
+}
+
+#[test]
+fn config_round_trips_through_json() {
+    use crate::config::Config;
+
+    let mut config = Config::default();
+    config.ch0_multipliers.set_sensitivity_multiplier(2);
+    config.otp_bank2.set_target(AtiTarget::Counts512);
+
+    let json = serde_json::to_string(&config).unwrap();
+    assert!(json.contains(r#""sensitivity_multiplier":2"#));
+    assert!(json.contains(r#""target":"Counts512""#));
+    assert_eq!(serde_json::from_str::<Config>(&json).unwrap(), config);
+
+    let flags = registers::MainEvents::PROX | registers::MainEvents::TOUCH;
+    let json = serde_json::to_string(&flags).unwrap();
+    assert_eq!(
+        serde_json::from_str::<registers::MainEvents>(&json).unwrap(),
+        flags
+    );
+
+    let out_of_range = r#"{"compensation_multiplier":16,"sensitivity_multiplier":0}"#;
+    assert!(serde_json::from_str::<registers::ChannelMultiplier>(out_of_range).is_err());
+}
+
+#[test]
+fn bitfields_are_raw_bytes_in_binary_formats() {
+    let movement = registers::Movement::default();
+    let bytes = postcard::to_allocvec(&movement).unwrap();
+    assert_eq!(bytes, [0x34]);
+    assert_eq!(
+        postcard::from_bytes::<registers::Movement>(&bytes).unwrap(),
+        movement
+    );
+}
+
+#[test]
+fn transactions_round_trip_through_postcard() {
+    let t: record::Transaction = "write_read 44 [15] -> [01 03 e8]".parse().unwrap();
+    let bytes = postcard::to_allocvec(&t).unwrap();
+    assert_eq!(
+        postcard::from_bytes::<record::Transaction>(&bytes).unwrap(),
+        t
+    );
+}
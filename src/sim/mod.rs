@@ -0,0 +1,651 @@
+//! Software model of the IQS231 register map, for testing code using `Iqs231` on a host.
+//!
+//! `SimIqs231` implements the I²C traits (embedded-hal v0.2, and v1.0 / async with the `eh1` and
+//! `async` features) and behaves like the device on the bus: every read is prepended with the
+//! `MainEvents` byte, the register address auto-increments, read-only registers can not be written
+//! and `Commands` are executed (`STANDALONE` disables the I²C interface). Writing a multiplier or
+//! compensation register switches off automatic ATI (`UiFlags::UI_AUTO_ATI_OFF`).
+//! Counts, flags and resets can be injected by the test.
+//!
+//! `SimIqs231` is a cheap handle to the shared device state: keep a clone around to inject
+//! events while the driver owns the other one.
+//!
+//! Attach a `SensorModel` to simulate the proximity channel behaviour over time instead of
+//! injecting static count values.
+use std::{cell::RefCell, rc::Rc, vec::Vec};
+
+use embedded_hal::blocking::{
+    delay::DelayMs,
+    i2c::{Read, Write, WriteRead},
+};
+
+use crate::{
+    device::I2cAddress,
+    registers::{
+        AcFilter, AtiFlags, Commands, DebugEvents, EventFlags, MainEvents, OtpBank1, OtpBank2,
+        Register, SystemFlags, UiFlags, PRODUCT_NUMBER, REGISTER_COUNT,
+    },
+};
+
+mod model;
+pub use model::{compensated_count, SensorModel};
+
+/// Time automatic ATI takes in the simulator
+pub const ATI_DURATION_MS: u32 = 150;
+
+/// `TimerRedoAti` after a failed ATI (× 100ms)
+const REDO_ATI_TICKS: u8 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimError {
+    /// Addressed another device, or the device is in standalone mode
+    Nack,
+    /// Access beyond the last register
+    InvalidRegister(u8),
+    /// Write to a register that is not writable
+    ReadOnly(Register),
+}
+
+#[derive(Clone)]
+pub struct SimIqs231 {
+    state: Rc<RefCell<State>>,
+}
+
+struct State {
+    address: u8,
+    /// Address the device answers at after a reset
+    otp_address: I2cAddress,
+    regs: [u8; REGISTER_COUNT],
+    pointer: u8,
+    main_events: MainEvents,
+    /// Boot flags are reported once, on the first read after a reset
+    boot_events: MainEvents,
+    standalone: bool,
+    /// Drop received `Commands`
+    ignore_commands: bool,
+    ulp_mode: bool,
+    ac_filter: bool,
+    ati_count: usize,
+    /// Time left until a running ATI completes
+    ati_remaining_ms: Option<u32>,
+    model: Option<SensorModel>,
+    /// Time advanced but not yet processed by the model
+    pending_ms: u32,
+}
+
+impl Default for SimIqs231 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimIqs231 {
+    /// Freshly powered device at the default address, reporting `MainEvents::COLD_BOOT`
+    pub fn new() -> Self {
+        let mut state = State {
+            address: I2cAddress::Default as u8,
+            otp_address: I2cAddress::Default,
+            regs: [0; REGISTER_COUNT],
+            pointer: 0,
+            main_events: MainEvents::empty(),
+            boot_events: MainEvents::empty(),
+            standalone: false,
+            ignore_commands: false,
+            ulp_mode: false,
+            ac_filter: true,
+            ati_count: 0,
+            ati_remaining_ms: None,
+            model: None,
+            pending_ms: 0,
+        };
+        state.boot(MainEvents::COLD_BOOT);
+
+        Self {
+            state: Rc::new(RefCell::new(state)),
+        }
+    }
+
+    /// Device programmed (OTP) to answer at `address`
+    pub fn with_address(self, address: I2cAddress) -> Self {
+        let mut state = self.state.borrow_mut();
+        state.address = address as u8;
+        state.otp_address = address;
+        state.regs[Register::OtpBank1 as usize] =
+            OtpBank1::new().with_i2c_addr(address).into_bytes()[0];
+        drop(state);
+        self
+    }
+
+    /// Address the device currently answers at
+    pub fn address(&self) -> u8 {
+        self.state.borrow().address
+    }
+
+    /// Power cycle: registers revert to their defaults and `COLD_BOOT` is reported
+    pub fn inject_cold_boot(&self) {
+        self.state.borrow_mut().boot(MainEvents::COLD_BOOT);
+    }
+
+    /// Watchdog/brown-out reset: registers revert to their defaults and `WARM_BOOT` is reported
+    pub fn inject_warm_boot(&self) {
+        self.state.borrow_mut().boot(MainEvents::WARM_BOOT);
+    }
+
+    /// Set any register, bypassing the writability check
+    pub fn set_register(&self, register: Register, value: u8) {
+        self.state.borrow_mut().regs[register as usize] = value;
+    }
+
+    pub fn register(&self, register: Register) -> u8 {
+        self.state.borrow().regs[register as usize]
+    }
+
+    /// Set a 16-bit count register, `register` being the high byte
+    pub fn set_count(&self, register: Register, count: u16) {
+        let [hi, lo] = count.to_be_bytes();
+        let mut state = self.state.borrow_mut();
+        state.regs[register as usize] = hi;
+        state.regs[register as usize + 1] = lo;
+    }
+
+    /// Read a 16-bit count register, `register` being the high byte
+    pub fn count(&self, register: Register) -> u16 {
+        let state = self.state.borrow();
+        u16::from_be_bytes([
+            state.regs[register as usize],
+            state.regs[register as usize + 1],
+        ])
+    }
+
+    /// Level events reported in front of every read (boot flags are added on top)
+    pub fn set_main_events(&self, events: MainEvents) {
+        self.state.borrow_mut().main_events = events;
+    }
+
+    pub fn set_event_flags(&self, flags: EventFlags) {
+        self.set_register(Register::EventFlags, flags.bits());
+    }
+
+    pub fn set_debug_events(&self, events: DebugEvents) {
+        self.set_register(Register::DebugEvents, events.bits());
+    }
+
+    pub fn set_system_flags(&self, flags: SystemFlags) {
+        self.set_register(Register::System_Flags, flags.bits());
+    }
+
+    pub fn set_ui_flags(&self, flags: UiFlags) {
+        self.set_register(Register::UI_Flags, flags.bits());
+    }
+
+    pub fn is_standalone(&self) -> bool {
+        self.state.borrow().standalone
+    }
+
+    /// Drop received `Commands`, as if the device missed them
+    pub fn set_ignore_commands(&self, ignore: bool) {
+        self.state.borrow_mut().ignore_commands = ignore;
+    }
+
+    pub fn ulp_mode(&self) -> bool {
+        self.state.borrow().ulp_mode
+    }
+
+    pub fn ac_filter(&self) -> bool {
+        self.state.borrow().ac_filter
+    }
+
+    /// Number of `Commands::ATI_CH0` received. ATI takes `ATI_DURATION_MS` (`SystemFlags::ATI_MODE`
+    /// is set meanwhile); with a model `electrode` it tunes CH0 to the model `ati_target`, or flags an
+    /// ATI error if the target can not be reached.
+    pub fn ati_count(&self) -> usize {
+        self.state.borrow().ati_count
+    }
+
+    /// Simulate the proximity channel with `model` from now on, instead of static counts
+    pub fn attach_model(&self, mut model: SensorModel) {
+        model.reseed();
+        let mut state = self.state.borrow_mut();
+        state.model = Some(model);
+        state.pending_ms = 0;
+    }
+
+    pub fn model(&self) -> Option<SensorModel> {
+        self.state.borrow().model
+    }
+
+    /// Counts removed from the raw count by the object near the electrode (needs a model)
+    pub fn set_signal(&self, counts: u16) {
+        if let Some(model) = self.state.borrow_mut().model.as_mut() {
+            model.set_signal(counts);
+        }
+    }
+
+    /// Movement on the movement channel (needs a model)
+    pub fn set_moving(&self, moving: bool) {
+        if let Some(model) = self.state.borrow_mut().model.as_mut() {
+            model.set_moving(moving);
+        }
+    }
+
+    /// Let `ms` milliseconds pass, running the model once per sample period
+    /// (`OtpBank3::sample_rate`). Returns the `MainEvents` after every processed sample.
+    pub fn advance(&self, ms: u32) -> Vec<MainEvents> {
+        self.state.borrow_mut().advance(ms)
+    }
+
+    /// Run a script of `(duration_ms, signal)` segments, see `advance()`
+    pub fn run_script(&self, script: &[(u32, u16)]) -> Vec<MainEvents> {
+        let mut events = Vec::new();
+        for &(duration_ms, signal) in script {
+            self.set_signal(signal);
+            events.extend(self.advance(duration_ms));
+        }
+        events
+    }
+
+    fn handle_write(&mut self, address: u8, bytes: &[u8]) -> Result<(), SimError> {
+        self.state.borrow_mut().handle_write(address, bytes)
+    }
+
+    fn handle_read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), SimError> {
+        self.state.borrow_mut().handle_read(address, buffer)
+    }
+}
+
+impl State {
+    fn boot(&mut self, events: MainEvents) {
+        self.regs = [0; REGISTER_COUNT];
+        self.address = self.otp_address as u8;
+        self.regs[Register::OtpBank1 as usize] =
+            OtpBank1::new().with_i2c_addr(self.otp_address).into_bytes()[0];
+        self.regs[Register::ProductNumber as usize] = PRODUCT_NUMBER;
+        self.regs[Register::SoftwareVersion as usize] = 0x06;
+        self.regs[Register::Movement as usize] = 0x34;
+        self.regs[Register::TouchThreshold as usize] = 0x07;
+        self.regs[Register::TempInterferenceThreshold as usize] = 0x03;
+        self.regs[Register::System_Flags as usize] = SystemFlags::I2C.bits();
+        self.pointer = 0;
+        self.main_events = MainEvents::empty();
+        self.boot_events = events;
+        self.standalone = false;
+        self.ulp_mode = false;
+        self.ac_filter = true;
+        self.pending_ms = 0;
+        self.ati_remaining_ms = None;
+        if let Some(model) = self.model.as_mut() {
+            model.reseed();
+        }
+    }
+
+    fn advance(&mut self, ms: u32) -> Vec<MainEvents> {
+        if let Some(remaining) = self.ati_remaining_ms {
+            match remaining.checked_sub(ms) {
+                Some(remaining) if remaining > 0 => self.ati_remaining_ms = Some(remaining),
+                _ => self.finish_ati(),
+            }
+        }
+
+        let mut events = Vec::new();
+        let Some(model) = self.model.as_mut() else {
+            return events;
+        };
+
+        let period = model::sample_period_ms(&self.regs);
+        self.pending_ms += ms;
+        while self.pending_ms >= period {
+            self.pending_ms -= period;
+            if !self.main_events.contains(MainEvents::SENSING_DISABLED) {
+                model.step(&mut self.regs, &mut self.main_events, period);
+            }
+            events.push(self.main_events);
+        }
+        events
+    }
+
+    fn start_ati(&mut self) {
+        self.ati_count += 1;
+        self.ati_remaining_ms = Some(ATI_DURATION_MS);
+        self.regs[Register::UI_Flags as usize] &= !UiFlags::UI_AUTO_ATI_OFF.bits();
+        self.regs[Register::System_Flags as usize] |= SystemFlags::ATI_MODE.bits();
+        self.regs[Register::ATI_Flags as usize] = AtiFlags::CH0_ATI_BUSY.bits();
+        self.regs[Register::EventFlags as usize] &= !EventFlags::CH0_ATI_ERROR.bits();
+        self.regs[Register::DebugEvents as usize] &= !DebugEvents::ATI_ERROR.bits();
+    }
+
+    fn finish_ati(&mut self) {
+        self.ati_remaining_ms = None;
+        self.regs[Register::System_Flags as usize] &= !SystemFlags::ATI_MODE.bits();
+        self.regs[Register::ATI_Flags as usize] = 0;
+
+        let Some(model) = self.model.as_ref() else {
+            return;
+        };
+        let Some(electrode) = model.electrode else {
+            return;
+        };
+        let base = OtpBank2::from_bytes([self.regs[Register::OtpBank2 as usize]])
+            .base_value()
+            .counts();
+
+        match model::auto_ati(electrode, base, model.ati_target) {
+            Some((mult, compensation)) => {
+                self.regs[Register::CH0_Multipliers as usize] = mult.into_bytes()[0];
+                self.regs[Register::CH0_Compensation as usize] = compensation;
+                self.regs[Register::DebugEvents as usize] |= DebugEvents::CH0_ATI.bits();
+            }
+            None => {
+                self.regs[Register::ATI_Flags as usize] = AtiFlags::CH0_COMPENSATION_MAX.bits();
+                self.regs[Register::EventFlags as usize] |= EventFlags::CH0_ATI_ERROR.bits();
+                self.regs[Register::DebugEvents as usize] |= DebugEvents::ATI_ERROR.bits();
+                self.regs[Register::TimerRedoAti as usize] = REDO_ATI_TICKS;
+            }
+        }
+    }
+
+    fn check_address(&self, address: u8) -> Result<(), SimError> {
+        if self.standalone || address != self.address {
+            Err(SimError::Nack)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn handle_write(&mut self, address: u8, bytes: &[u8]) -> Result<(), SimError> {
+        self.check_address(address)?;
+
+        let Some((&reg, values)) = bytes.split_first() else {
+            return Ok(());
+        };
+        self.pointer = reg;
+
+        let mut new_address = None;
+        for &value in values {
+            let reg = Register::try_from(self.pointer)
+                .map_err(|_| SimError::InvalidRegister(self.pointer))?;
+            if !reg.is_writable() {
+                return Err(SimError::ReadOnly(reg));
+            }
+
+            match reg {
+                Register::Commands if self.ignore_commands => {}
+                Register::Commands => self.execute(Commands::from_bits_retain(value)),
+                // the new address takes effect after this transaction
+                Register::OtpBank1 => {
+                    self.regs[reg as usize] = value;
+                    let otp = OtpBank1::from_bytes([value]);
+                    self.ac_filter = otp.ac_filter() != AcFilter::Off;
+                    new_address = Some(otp.i2c_addr() as u8);
+                }
+                // writing the ATI results over I²C switches off automatic ATI
+                Register::CH0_Multipliers
+                | Register::CH0_Compensation
+                | Register::CH1_Multipliers
+                | Register::CH1_Compensation => {
+                    self.regs[reg as usize] = value;
+                    self.regs[Register::UI_Flags as usize] |= UiFlags::UI_AUTO_ATI_OFF.bits();
+                }
+                _ => self.regs[reg as usize] = value,
+            }
+            self.pointer += 1;
+        }
+        if let Some(address) = new_address {
+            self.address = address;
+        }
+        Ok(())
+    }
+
+    fn handle_read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), SimError> {
+        self.check_address(address)?;
+
+        let Some((first, values)) = buffer.split_first_mut() else {
+            return Ok(());
+        };
+        *first = (self.main_events | self.boot_events).bits();
+        self.boot_events = MainEvents::empty();
+
+        for value in values {
+            if self.pointer as usize >= REGISTER_COUNT {
+                return Err(SimError::InvalidRegister(self.pointer));
+            }
+            *value = self.regs[self.pointer as usize];
+            self.pointer += 1;
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, commands: Commands) {
+        if commands.contains(Commands::ATI_CH0) {
+            self.start_ati();
+        }
+        if commands.contains(Commands::DISABLE_SENSING) {
+            self.main_events.insert(MainEvents::SENSING_DISABLED);
+            self.regs[Register::UI_Flags as usize] |= UiFlags::UI_SENSING_DISABLD.bits();
+        }
+        if commands.contains(Commands::ENABLE_SENSING) {
+            self.main_events.remove(MainEvents::SENSING_DISABLED);
+            self.regs[Register::UI_Flags as usize] &= !UiFlags::UI_SENSING_DISABLD.bits();
+        }
+        if commands.contains(Commands::TOGGLE_AC_FILTER) {
+            self.ac_filter = !self.ac_filter;
+        }
+        if commands.contains(Commands::TOGGLE_ULP_MODE) {
+            self.ulp_mode = !self.ulp_mode;
+        }
+        if commands.contains(Commands::STANDALONE) {
+            self.standalone = true;
+        }
+    }
+}
+
+impl Read for SimIqs231 {
+    type Error = SimError;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), SimError> {
+        self.handle_read(address, buffer)
+    }
+}
+
+impl Write for SimIqs231 {
+    type Error = SimError;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), SimError> {
+        self.handle_write(address, bytes)
+    }
+}
+
+impl WriteRead for SimIqs231 {
+    type Error = SimError;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), SimError> {
+        self.handle_write(address, bytes)?;
+        self.handle_read(address, buffer)
+    }
+}
+
+/// Delaying on the simulator advances its time, see `advance()`
+impl DelayMs<u32> for SimIqs231 {
+    fn delay_ms(&mut self, ms: u32) {
+        self.advance(ms);
+    }
+}
+
+#[cfg(feature = "eh1")]
+impl embedded_hal_1::i2c::Error for SimError {
+    fn kind(&self) -> embedded_hal_1::i2c::ErrorKind {
+        use embedded_hal_1::i2c::{ErrorKind, NoAcknowledgeSource};
+        match self {
+            SimError::Nack => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+            SimError::InvalidRegister(_) | SimError::ReadOnly(_) => {
+                ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "eh1")]
+impl embedded_hal_1::i2c::ErrorType for SimIqs231 {
+    type Error = SimError;
+}
+
+#[cfg(feature = "eh1")]
+impl embedded_hal_1::i2c::I2c for SimIqs231 {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [embedded_hal_1::i2c::Operation<'_>],
+    ) -> Result<(), SimError> {
+        use embedded_hal_1::i2c::Operation;
+        for op in operations {
+            match op {
+                Operation::Write(bytes) => self.handle_write(address, bytes)?,
+                Operation::Read(buffer) => self.handle_read(address, buffer)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "eh1", feature = "async"))]
+impl embedded_hal_async::i2c::I2c for SimIqs231 {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [embedded_hal_1::i2c::Operation<'_>],
+    ) -> Result<(), SimError> {
+        embedded_hal_1::i2c::I2c::transaction(self, address, operations)
+    }
+}
+
+#[cfg(test)]
+use crate::{registers::ProximityThreshold, Error, Iqs231};
+
+/// Driver talking to a freshly powered simulator, optionally running `model`
+#[cfg(test)]
+pub(crate) fn test_device(model: Option<SensorModel>) -> (SimIqs231, Iqs231<SimIqs231>) {
+    let sim = SimIqs231::new();
+    if let Some(model) = model {
+        sim.attach_model(model);
+    }
+    (sim.clone(), Iqs231::new(sim))
+}
+
+#[test]
+fn sim_serves_register_api() {
+    let sim = SimIqs231::new();
+    let mut iqs = Iqs231::new(sim.clone());
+    assert_eq!(iqs.get_prod_nr().unwrap(), PRODUCT_NUMBER);
+
+    iqs.set_touch_threshold(100).unwrap();
+    assert_eq!(iqs.get_touch_threshold().unwrap().value, 100);
+
+    sim.set_count(Register::CH0_ACF_H, 1234);
+    sim.set_main_events(MainEvents::PROX);
+
+    let count = iqs.get_prox_filtered_count().unwrap();
+    assert_eq!(count.value, 1234);
+    assert_eq!(count.main_events, MainEvents::PROX);
+}
+
+#[test]
+fn sim_reports_boot_flags_once() {
+    let mut sim = SimIqs231::new();
+    let mut buf = [0u8; 1];
+    sim.read(0x44, &mut buf).unwrap();
+    assert_eq!(buf[0], MainEvents::COLD_BOOT.bits());
+    sim.read(0x44, &mut buf).unwrap();
+    assert_eq!(buf[0], 0);
+}
+
+#[test]
+fn sim_enforces_writability_and_standalone() {
+    let mut sim = SimIqs231::new();
+    assert_eq!(
+        sim.write(0x44, &[Register::CH0_ACF_H as u8, 0]),
+        Err(SimError::ReadOnly(Register::CH0_ACF_H))
+    );
+    assert_eq!(sim.write(0x45, &[0]), Err(SimError::Nack));
+
+    let iqs = Iqs231::new(sim.clone());
+    assert!(matches!(
+        Iqs231::new(sim.clone()).send_commands(Commands::STANDALONE),
+        Err(Error::ShutdownCommandNotAllowed)
+    ));
+    iqs.into_standalone().unwrap();
+    assert!(sim.is_standalone());
+    assert_eq!(sim.read(0x44, &mut [0u8; 1]), Err(SimError::Nack));
+}
+
+#[test]
+fn model_detects_proximity_and_touch() {
+    let sim = SimIqs231::new();
+    let mut iqs = Iqs231::new(sim.clone());
+    iqs.set_proximity_threshold(ProximityThreshold::Counts8)
+        .unwrap();
+    iqs.set_touch_threshold(40).unwrap();
+    sim.attach_model(SensorModel::new(1000));
+
+    // untouched: no events, LTA settles at the base count
+    let events = sim.run_script(&[(1000, 0)]);
+    assert!(events.iter().all(|e| e.is_empty()));
+    assert_eq!(iqs.get_prox_reference_count().unwrap().value, 1000);
+
+    // approach: proximity, then touch
+    let events = sim.run_script(&[(300, 20), (300, 60)]);
+    let first_prox = events.iter().position(|e| e.contains(MainEvents::PROX));
+    let first_touch = events.iter().position(|e| e.contains(MainEvents::TOUCH));
+    assert!(first_prox.unwrap() < first_touch.unwrap());
+
+    let count = iqs.get_prox_filtered_count().unwrap();
+    assert!(count
+        .main_events
+        .contains(MainEvents::PROX | MainEvents::TOUCH));
+    assert!(count.value < 960);
+    // reference is halted while in proximity
+    let lta = iqs.get_prox_reference_count().unwrap().value;
+    sim.advance(1000);
+    assert_eq!(iqs.get_prox_reference_count().unwrap().value, lta);
+
+    // leave: proximity ends
+    let events = sim.run_script(&[(1000, 0)]);
+    assert!(events.last().unwrap().is_empty());
+}
+
+#[test]
+fn model_quick_release_and_halt_timeout() {
+    let sim = SimIqs231::new();
+    let mut iqs = Iqs231::new(sim.clone());
+    let mut otp2 = crate::registers::OtpBank2::new();
+    otp2.set_quick_release(true);
+    iqs.set_otp_bank2(otp2).unwrap();
+    iqs.set_quick_release(
+        crate::registers::QuickRelease::new()
+            .with_beta(4)
+            .with_threshold(crate::registers::QuickReleaseThreshold::Qrt10),
+    )
+    .unwrap();
+    sim.attach_model(SensorModel::new(1000));
+
+    // object settles close to the sensor, then is removed suddenly
+    sim.run_script(&[(500, 0), (2000, 50)]);
+    let events = sim.run_script(&[(300, 0)]);
+    assert!(events.iter().any(|e| e.contains(MainEvents::RELEASE)));
+    assert!(!events.last().unwrap().contains(MainEvents::PROX));
+
+    // without movement the LTA halt times out (5 minutes by default) and the reference re-seeds
+    sim.run_script(&[(1000, 50)]);
+    assert!(iqs.get_lta_halt_timer().unwrap().value > 0);
+    let events = sim.advance(5 * 60 * 1000);
+    assert!(!events.last().unwrap().contains(MainEvents::PROX));
+    assert_eq!(iqs.get_lta_halt_timer().unwrap().value, 0);
+}
+
+#[cfg(feature = "eh1")]
+#[test]
+fn sim_serves_eh1_driver() {
+    let sim = SimIqs231::new();
+    let mut iqs = Iqs231::new(crate::Eh1(sim.clone()));
+    iqs.set_ch0_compensation(77).unwrap();
+    assert_eq!(iqs.get_ch0_compensation().unwrap().value, 77);
+    assert_eq!(sim.register(Register::CH0_Compensation), 77);
+}
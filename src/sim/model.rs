@@ -0,0 +1,223 @@
+//! Behavioural model of the proximity channel (CH0), see `SimIqs231::attach_model()`.
+//!
+//! Each sample the raw count follows the scripted signal (`base_count - signal`), the filtered
+//! count (`CH0_ACF`) tracks the raw count and the long term average (`CH0_LTA`) slowly tracks the
+//! filtered count. Proximity and touch are decided on the delta `LTA - ACF` against the
+//! `ProximityThreshold` and `TouchThreshold` registers. While in proximity the LTA is halted until
+//! the `Movement` time-out expires (movement restarts the `LtaHaltTimer`), and quick release
+//! re-seeds the LTA when the count moves back towards the reference faster than the
+//! `QuickRelease` threshold.
+//!
+//! With `electrode` set, the count without signal follows the CH0 multiplier and compensation
+//! registers instead of `base_count`, for exercising (manual) ATI.
+use crate::{
+    device::touch_threshold_from_reg,
+    registers::{
+        ChannelMultiplier, EventFlags, MainEvents, Movement, OtpBank2, OtpBank3,
+        ProximityThreshold, QuickRelease, Register, SystemFlags, UiFlags, REGISTER_COUNT,
+    },
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct SensorModel {
+    /// Raw count without any object near the electrode (the ATI target)
+    pub base_count: u16,
+    /// Filtered count IIR filter coefficient: `acf += (raw - acf) / 2^acf_shift`
+    pub acf_shift: u8,
+    /// Long term average IIR filter coefficient: `lta += (acf - lta) / 2^lta_shift`
+    pub lta_shift: u8,
+    /// Uncompensated count at sensitivity multiplier 0, see `compensated_count()`
+    pub electrode: Option<u16>,
+    /// Count automatic ATI (`Commands::ATI_CH0`) tunes to, when `electrode` is set
+    pub ati_target: u16,
+
+    signal: u16,
+    moving: bool,
+    acf: i32,
+    lta: i32,
+    qrd: i32,
+    prox: bool,
+    halt_ticks: u16,
+    tick_ms: u32,
+}
+
+impl SensorModel {
+    pub fn new(base_count: u16) -> Self {
+        Self {
+            base_count,
+            acf_shift: 1,
+            lta_shift: 6,
+            electrode: None,
+            ati_target: base_count,
+            signal: 0,
+            moving: false,
+            acf: base_count as i32,
+            lta: base_count as i32,
+            qrd: base_count as i32,
+            prox: false,
+            halt_ticks: 0,
+            tick_ms: 0,
+        }
+    }
+
+    /// Counts removed from the raw count by the object near the electrode
+    pub fn set_signal(&mut self, counts: u16) {
+        self.signal = counts;
+    }
+
+    /// Movement detected on the movement channel (restarts the LTA halt timer)
+    pub fn set_moving(&mut self, moving: bool) {
+        self.moving = moving;
+    }
+
+    /// Restart filtering from the current input, as the device does after a reset
+    pub(crate) fn reseed(&mut self) {
+        let raw = self.base_count.saturating_sub(self.signal) as i32;
+        self.acf = raw;
+        self.lta = raw;
+        self.qrd = raw;
+        self.prox = false;
+        self.halt_ticks = 0;
+        self.tick_ms = 0;
+    }
+
+    /// Proximity delta (`LTA - ACF`)
+    pub fn delta(&self) -> i32 {
+        self.lta - self.acf
+    }
+
+    /// Process one sample, reading the settings from and writing the results to the register map.
+    /// `sample_ms` is the sample period.
+    pub(crate) fn step(
+        &mut self,
+        regs: &mut [u8; REGISTER_COUNT],
+        main_events: &mut MainEvents,
+        sample_ms: u32,
+    ) {
+        let reg = |r: Register| regs[r as usize];
+        let prox_thresh =
+            ProximityThreshold::from(reg(Register::ProximityThreshold)).counts() as i32;
+        let touch_thresh = touch_threshold_from_reg(reg(Register::TouchThreshold)) as i32;
+        let quick_release = QuickRelease::from_bytes([reg(Register::QuickRelease)]);
+        let quick_release_enabled = OtpBank2::from_bytes([reg(Register::OtpBank2)]).quick_release();
+        let timeout_ticks = Movement::from_bytes([reg(Register::Movement)])
+            .timeout()
+            .minutes() as u16
+            * 600;
+
+        main_events.remove(MainEvents::RELEASE);
+
+        if let Some(electrode) = self.electrode {
+            self.base_count = compensated_count(
+                electrode,
+                ChannelMultiplier::from_bytes([reg(Register::CH0_Multipliers)]),
+                reg(Register::CH0_Compensation),
+            );
+        }
+        let raw = self.base_count.saturating_sub(self.signal) as i32;
+        self.acf += (raw - self.acf) / (1 << self.acf_shift);
+
+        // LTA halt timer counts down in 100ms ticks
+        self.tick_ms += sample_ms;
+        let ticks = (self.tick_ms / 100) as u16;
+        self.tick_ms %= 100;
+        if self.prox && self.moving {
+            self.halt_ticks = timeout_ticks;
+        } else {
+            self.halt_ticks = self.halt_ticks.saturating_sub(ticks);
+        }
+
+        let mut released = false;
+        if self.prox {
+            // quick release reference follows the filtered count, with `beta` as filter shift
+            self.qrd += (self.acf - self.qrd) / (1 << quick_release.beta());
+            let recovery = self.acf - self.qrd;
+            if quick_release_enabled && recovery >= quick_release.threshold().counts() as i32 {
+                released = true;
+            }
+
+            if released || self.halt_ticks == 0 {
+                self.lta = self.acf;
+                self.prox = false;
+                self.halt_ticks = 0;
+            } else if self.delta() < prox_thresh {
+                self.prox = false;
+                self.halt_ticks = 0;
+            }
+        } else {
+            self.lta += (self.acf - self.lta) / (1 << self.lta_shift);
+            self.qrd = self.acf;
+
+            if self.delta() >= prox_thresh {
+                self.prox = true;
+                self.halt_ticks = timeout_ticks;
+            }
+        }
+        let touch = self.prox && self.delta() >= touch_thresh;
+
+        main_events.set(MainEvents::PROX, self.prox);
+        main_events.set(MainEvents::TOUCH, touch);
+        main_events.set(MainEvents::RELEASE, released);
+
+        let mut flags = EventFlags::from_bits_retain(reg(Register::EventFlags));
+        flags.set(EventFlags::CH0_PROX, self.prox);
+        flags.set(EventFlags::CH0_TOUCH, touch);
+        flags.set(EventFlags::CH1_MOVEMENT, self.moving);
+
+        let mut sys = SystemFlags::from_bits_retain(reg(Register::System_Flags));
+        sys.set(SystemFlags::CH0_LTA_HALTED, self.prox);
+
+        let mut ui = UiFlags::from_bits_retain(reg(Register::UI_Flags));
+        ui.set(UiFlags::OUTPUT_ACTIVE, self.prox);
+        ui.set(UiFlags::QUICK_RELEASE, released);
+
+        regs[Register::EventFlags as usize] = flags.bits();
+        regs[Register::System_Flags as usize] = sys.bits();
+        regs[Register::UI_Flags as usize] = ui.bits();
+        write16(regs, Register::CH0_ACF_H, self.acf);
+        write16(regs, Register::CH0_LTA_H, self.lta);
+        write16(regs, Register::CH0_QRD_H, self.qrd);
+        write16(regs, Register::LtaHaltTimer_H, self.halt_ticks as i32);
+    }
+}
+
+/// Count for the given channel settings: every sensitivity multiplier step doubles the
+/// uncompensated `electrode` count, compensation adds `compensation × (multiplier + 1) / 256`
+/// times that on top.
+pub fn compensated_count(electrode: u16, multipliers: ChannelMultiplier, compensation: u8) -> u16 {
+    let base = (electrode as u32) << multipliers.sensitivity_multiplier();
+    let added =
+        base * compensation as u32 * (multipliers.compensation_multiplier() as u32 + 1) / 256;
+    (base + added).min(u16::MAX as u32) as u16
+}
+
+/// Settings automatic ATI would choose: the sensitivity multiplier giving the uncompensated count
+/// closest to `base`, then the smallest compensation (multiplier) reaching `target`.
+/// `None` if the target can not be reached.
+pub(crate) fn auto_ati(electrode: u16, base: u16, target: u16) -> Option<(ChannelMultiplier, u8)> {
+    let sensitivity = (0..=3)
+        .min_by_key(|&s| (electrode << s).abs_diff(base))
+        .unwrap_or(0);
+
+    (0..=15).find_map(|compensation_multiplier| {
+        let mult = ChannelMultiplier::new()
+            .with_sensitivity_multiplier(sensitivity)
+            .with_compensation_multiplier(compensation_multiplier);
+        (0..=255u8)
+            .find(|&c| compensated_count(electrode, mult, c) >= target)
+            .map(|c| (mult, c))
+    })
+}
+
+/// Sample period as configured in `OtpBank3::sample_rate`
+pub(crate) fn sample_period_ms(regs: &[u8; REGISTER_COUNT]) -> u32 {
+    OtpBank3::from_bytes([regs[Register::OtpBank3 as usize]])
+        .sample_rate()
+        .period_ms() as u32
+}
+
+fn write16(regs: &mut [u8; REGISTER_COUNT], register: Register, value: i32) {
+    let [hi, lo] = (value.clamp(0, u16::MAX as i32) as u16).to_be_bytes();
+    regs[register as usize] = hi;
+    regs[register as usize + 1] = lo;
+}
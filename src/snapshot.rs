@@ -0,0 +1,143 @@
+use num_enum::TryFromPrimitive;
+
+use crate::{
+    device::touch_threshold_from_reg,
+    registers::{
+        AtiFlags, ChannelMultiplier, DebugEvents, EventFlags, Movement, OtpBank1, OtpBank2,
+        OtpBank3, ProximityThreshold, QuickRelease, Register, SoftwareVersion, SystemFlags,
+        UiFlags, REGISTER_COUNT,
+    },
+};
+
+/// Decoded contents of the complete register map (`ProductNumber` up to `TimerRedoAti`),
+/// as read by `Iqs231::read_all()` in a single burst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceSnapshot {
+    pub product_number: u8,
+    /// Raw software version, see `software_version()` for the decoded value
+    pub software_version: u8,
+    pub debug_events: DebugEvents,
+
+    pub otp_bank1: OtpBank1,
+    pub otp_bank2: OtpBank2,
+    pub otp_bank3: OtpBank3,
+    pub quick_release: QuickRelease,
+    pub movement: Movement,
+    /// Touch threshold in counts (4..=1024)
+    pub touch_threshold: u16,
+    pub proximity_threshold: ProximityThreshold,
+    pub temp_interference_threshold: u8,
+    pub ch0_multipliers: ChannelMultiplier,
+    pub ch0_compensation: u8,
+    pub ch1_multipliers: ChannelMultiplier,
+    pub ch1_compensation: u8,
+
+    pub system_flags: SystemFlags,
+    pub ui_flags: UiFlags,
+    pub ati_flags: AtiFlags,
+    pub event_flags: EventFlags,
+
+    /// Proximity channel: Filtered count value (0-2000)
+    pub prox_filtered_count: u16,
+    /// Proximity channel: Reference count value (Long term average) (0-2000)
+    pub prox_reference_count: u16,
+    /// Proximity channel: Quick release detect reference value (0-2000)
+    pub prox_quick_release_detect_reference: u16,
+    /// Movement channel: Filtered count value (0-2000)
+    pub move_filtered_count: u16,
+    /// Movement channel: Upper reference count value (0-2000)
+    pub move_upper_reference_count: u16,
+    /// Movement channel: Lower reference count value (0-2000)
+    pub move_lower_reference_count: u16,
+    /// Temperature channel: Unfiltered count value (0-2000)
+    pub move_unfiltered_count: u16,
+    /// Movement channel temperature reference (0-2000)
+    pub temp_reference: u16,
+
+    /// (0 – 255) × 100ms
+    pub lta_halt_timer: u16,
+    /// (0 – 50) x 100ms
+    pub filter_halt_timer: u8,
+    /// (0 – 10) x 100ms
+    pub timer_read_input: u8,
+    /// (0 – 255) × 100ms
+    pub timer_redo_ati: u8,
+}
+
+impl DeviceSnapshot {
+    /// Decode a raw register map, indexed by register address
+    pub fn from_registers(regs: &[u8; REGISTER_COUNT]) -> Self {
+        let reg = |r: Register| regs[r as usize];
+        let reg16 = |r: Register| u16::from_be_bytes([regs[r as usize], regs[r as usize + 1]]);
+
+        Self {
+            product_number: reg(Register::ProductNumber),
+            software_version: reg(Register::SoftwareVersion),
+            debug_events: DebugEvents::from_bits_retain(reg(Register::DebugEvents)),
+
+            otp_bank1: OtpBank1::from_bytes([reg(Register::OtpBank1)]),
+            otp_bank2: OtpBank2::from_bytes([reg(Register::OtpBank2)]),
+            otp_bank3: OtpBank3::from_bytes([reg(Register::OtpBank3)]),
+            quick_release: QuickRelease::from_bytes([reg(Register::QuickRelease)]),
+            movement: Movement::from_bytes([reg(Register::Movement)]),
+            touch_threshold: touch_threshold_from_reg(reg(Register::TouchThreshold)),
+            proximity_threshold: reg(Register::ProximityThreshold).into(),
+            temp_interference_threshold: reg(Register::TempInterferenceThreshold),
+            ch0_multipliers: ChannelMultiplier::from_bytes([reg(Register::CH0_Multipliers)]),
+            ch0_compensation: reg(Register::CH0_Compensation),
+            ch1_multipliers: ChannelMultiplier::from_bytes([reg(Register::CH1_Multipliers)]),
+            ch1_compensation: reg(Register::CH1_Compensation),
+
+            system_flags: SystemFlags::from_bits_retain(reg(Register::System_Flags)),
+            ui_flags: UiFlags::from_bits_retain(reg(Register::UI_Flags)),
+            ati_flags: AtiFlags::from_bits_retain(reg(Register::ATI_Flags)),
+            event_flags: EventFlags::from_bits_retain(reg(Register::EventFlags)),
+
+            prox_filtered_count: reg16(Register::CH0_ACF_H),
+            prox_reference_count: reg16(Register::CH0_LTA_H),
+            prox_quick_release_detect_reference: reg16(Register::CH0_QRD_H),
+            move_filtered_count: reg16(Register::CH1_ACF_H),
+            move_upper_reference_count: reg16(Register::CH1_UMOV_H),
+            move_lower_reference_count: reg16(Register::CH1_LMOV_H),
+            move_unfiltered_count: reg16(Register::CH1_RAW_H),
+            temp_reference: reg16(Register::Temperature_H),
+
+            lta_halt_timer: reg16(Register::LtaHaltTimer_H),
+            filter_halt_timer: reg(Register::FilterHaltTimer),
+            timer_read_input: reg(Register::TimerReadInput),
+            timer_redo_ati: reg(Register::TimerRedoAti),
+        }
+    }
+
+    /// Decoded software version, `None` if unknown
+    pub fn software_version(&self) -> Option<SoftwareVersion> {
+        SoftwareVersion::try_from_primitive(self.software_version).ok()
+    }
+}
+
+#[test]
+fn snapshot_decodes_register_map() {
+    let mut regs = [0u8; REGISTER_COUNT];
+    regs[Register::ProductNumber as usize] = 0x40;
+    regs[Register::SoftwareVersion as usize] = 0x06;
+    regs[Register::TouchThreshold as usize] = 0x07;
+    regs[Register::ProximityThreshold as usize] = 0x02;
+    regs[Register::EventFlags as usize] = 0x03;
+    regs[Register::CH0_ACF_H as usize] = 0x03;
+    regs[Register::CH0_ACF_L as usize] = 0x20;
+    regs[Register::TimerRedoAti as usize] = 0x7f;
+
+    let snap = DeviceSnapshot::from_registers(&regs);
+    assert_eq!(snap.product_number, 0x40);
+    assert_eq!(snap.software_version(), Some(SoftwareVersion::IQS231A));
+    assert_eq!(snap.touch_threshold, 32);
+    assert_eq!(snap.proximity_threshold, ProximityThreshold::Counts8);
+    assert_eq!(
+        snap.event_flags,
+        EventFlags::CH0_PROX | EventFlags::CH0_TOUCH
+    );
+    assert_eq!(snap.prox_filtered_count, 800);
+    assert_eq!(snap.timer_redo_ati, 0x7f);
+}
@@ -0,0 +1,234 @@
+//! Threshold tuning assistant working on recorded samples, see `recommend()`.
+//!
+//! Record a few windows of samples with nothing near the electrode and a few while touching
+//! (e.g. with `Iqs231::sample_counts()`), then let `recommend()` derive the thresholds from the
+//! observed noise and signal. The result can be applied with `Iqs231::apply_tuning()` or merged
+//! into a `Config` with `Recommendation::apply_to()`.
+use crate::{
+    config::Config,
+    metrics::{NoiseEstimator, NoiseStats},
+    registers::{ChannelMultiplier, ProximityThreshold, QuickRelease, QuickReleaseThreshold},
+    sampling::CountSample,
+};
+
+/// The proximity threshold should be at least this many times the untouched noise peak
+pub const PROX_NOISE_FACTOR: i32 = 2;
+/// Minimum ratio of touched signal to untouched noise (standard deviation) before a higher
+/// sensitivity multiplier is recommended
+pub const MIN_SNR: u32 = 10;
+
+const PROXIMITY_THRESHOLDS: [ProximityThreshold; 4] = [
+    ProximityThreshold::Counts4,
+    ProximityThreshold::Counts6,
+    ProximityThreshold::Counts8,
+    ProximityThreshold::Counts10,
+];
+
+const QUICK_RELEASE_THRESHOLDS: [QuickReleaseThreshold; 16] = [
+    QuickReleaseThreshold::Qrt10,
+    QuickReleaseThreshold::Qrt20,
+    QuickReleaseThreshold::Qrt25,
+    QuickReleaseThreshold::Qrt30,
+    QuickReleaseThreshold::Qrt50,
+    QuickReleaseThreshold::Qrt75,
+    QuickReleaseThreshold::Qrt100,
+    QuickReleaseThreshold::Qrt150,
+    QuickReleaseThreshold::Qrt200,
+    QuickReleaseThreshold::Qrt250,
+    QuickReleaseThreshold::Qrt300,
+    QuickReleaseThreshold::Qrt400,
+    QuickReleaseThreshold::Qrt500,
+    QuickReleaseThreshold::Qrt750,
+    QuickReleaseThreshold::Qrt850,
+    QuickReleaseThreshold::Qrt1000,
+];
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct TuningWarnings: u8 {
+        /// Noise exceeds the largest proximity threshold divided by `PROX_NOISE_FACTOR`
+        const NOISY = 0x01;
+        /// Touched signal does not clear the untouched noise peak
+        const WEAK_SIGNAL = 0x02;
+        /// Signal to noise ratio below `MIN_SNR`
+        const LOW_SNR = 0x04;
+        /// Touched signal too small for any quick release threshold, current setting kept
+        const QUICK_RELEASE_UNCHANGED = 0x08;
+    }
+}
+
+/// Margins of the recommended thresholds against the recorded data, in counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Margins {
+    /// Delta of all untouched windows
+    pub untouched: NoiseStats,
+    /// Largest absolute delta seen untouched
+    pub noise_peak: i32,
+    /// Smallest mean delta of the touched windows
+    pub touched_signal: i32,
+    /// Touched signal over untouched standard deviation
+    pub snr: u32,
+    /// Proximity threshold − noise peak
+    pub prox_above_noise: i32,
+    /// Touch threshold − noise peak
+    pub touch_above_noise: i32,
+    /// Touched signal − touch threshold
+    pub touch_below_signal: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Recommendation {
+    pub proximity_threshold: ProximityThreshold,
+    /// Touch threshold in counts, for `Iqs231::set_touch_threshold()`
+    pub touch_threshold: u16,
+    pub quick_release: QuickRelease,
+    pub ch0_multipliers: ChannelMultiplier,
+    pub margins: Margins,
+    pub warnings: TuningWarnings,
+}
+
+impl Recommendation {
+    /// Merge the recommended settings into `config`
+    pub fn apply_to(&self, config: &mut Config) {
+        config.proximity_threshold = self.proximity_threshold;
+        config.touch_threshold = self.touch_threshold;
+        config.quick_release = self.quick_release;
+        config.ch0_multipliers = self.ch0_multipliers;
+    }
+}
+
+/// Recommend thresholds from `untouched` and `touched` sample windows, recorded with the
+/// `current` configuration. Returns `None` without samples of either kind.
+///
+/// - `ProximityThreshold`: the smallest setting above `PROX_NOISE_FACTOR` × the noise peak
+/// - touch threshold: halfway between the proximity threshold and the touched signal
+/// - `QuickRelease` threshold: the largest setting up to half the touched signal
+/// - `ChannelMultiplier`: one step more sensitivity when the SNR is below `MIN_SNR`
+pub fn recommend(
+    current: &Config,
+    untouched: &[&[CountSample]],
+    touched: &[&[CountSample]],
+) -> Option<Recommendation> {
+    let untouched_stats = untouched
+        .iter()
+        .flat_map(|w| w.iter())
+        .map(CountSample::delta)
+        .collect::<NoiseEstimator>()
+        .stats();
+    let touched_signal = touched
+        .iter()
+        .filter(|w| !w.is_empty())
+        .map(|w| w.iter().map(CountSample::delta).collect::<NoiseEstimator>())
+        .map(|e| e.stats().mean)
+        .min()?;
+    if untouched_stats.samples == 0 {
+        return None;
+    }
+
+    let mut warnings = TuningWarnings::empty();
+    let noise_peak = untouched_stats.max.max(-untouched_stats.min).max(0);
+
+    let proximity_threshold = PROXIMITY_THRESHOLDS
+        .into_iter()
+        .find(|t| t.counts() as i32 > noise_peak * PROX_NOISE_FACTOR)
+        .unwrap_or_else(|| {
+            warnings |= TuningWarnings::NOISY;
+            ProximityThreshold::Counts10
+        });
+    let prox = proximity_threshold.counts() as i32;
+
+    if touched_signal <= noise_peak {
+        warnings |= TuningWarnings::WEAK_SIGNAL;
+    }
+
+    // touch threshold has a resolution of 4 counts (4..=1024)
+    let touch = (prox + (touched_signal - prox).max(0) / 2).clamp(4, 1024);
+    let touch_threshold = (touch & !0x03).max(4) as u16;
+
+    let mut quick_release = current.quick_release;
+    match QUICK_RELEASE_THRESHOLDS
+        .into_iter()
+        .rev()
+        .find(|t| t.counts() as i32 <= touched_signal / 2)
+    {
+        Some(threshold) => quick_release.set_threshold(threshold),
+        None => warnings |= TuningWarnings::QUICK_RELEASE_UNCHANGED,
+    }
+
+    let snr = touched_signal.max(0) as u32 / untouched_stats.std_dev.max(1);
+    let mut ch0_multipliers = current.ch0_multipliers;
+    if snr < MIN_SNR {
+        warnings |= TuningWarnings::LOW_SNR;
+        let sensitivity = ch0_multipliers.sensitivity_multiplier();
+        if sensitivity < 3 {
+            ch0_multipliers.set_sensitivity_multiplier(sensitivity + 1);
+        }
+    }
+
+    Some(Recommendation {
+        proximity_threshold,
+        touch_threshold,
+        quick_release,
+        ch0_multipliers,
+        margins: Margins {
+            untouched: untouched_stats,
+            noise_peak,
+            touched_signal,
+            snr,
+            prox_above_noise: prox - noise_peak,
+            touch_above_noise: touch_threshold as i32 - noise_peak,
+            touch_below_signal: touched_signal - touch_threshold as i32,
+        },
+        warnings,
+    })
+}
+
+#[cfg(test)]
+fn window(deltas: &[i32]) -> std::vec::Vec<CountSample> {
+    deltas
+        .iter()
+        .map(|d| CountSample {
+            prox_reference: 1000,
+            prox_filtered: (1000 - d) as u16,
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[test]
+fn recommends_from_clean_recording() {
+    let untouched = [window(&[0, 1, -1, 2, 0]), window(&[1, 0, -2, 0, 1])];
+    let touched = [window(&[118, 120, 122]), window(&[98, 100, 102])];
+    let untouched: std::vec::Vec<&[CountSample]> = untouched.iter().map(|w| &w[..]).collect();
+    let touched: std::vec::Vec<&[CountSample]> = touched.iter().map(|w| &w[..]).collect();
+
+    let rec = recommend(&Config::default(), &untouched, &touched).unwrap();
+    assert_eq!(rec.proximity_threshold, ProximityThreshold::Counts6);
+    assert_eq!(rec.touch_threshold, 52);
+    assert_eq!(rec.quick_release.threshold(), QuickReleaseThreshold::Qrt50);
+    assert_eq!(rec.ch0_multipliers, Config::default().ch0_multipliers);
+    assert!(rec.warnings.is_empty());
+    assert_eq!(rec.margins.noise_peak, 2);
+    assert_eq!(rec.margins.touch_below_signal, 48);
+
+    let mut config = Config::default();
+    rec.apply_to(&mut config);
+    assert_eq!(config.touch_threshold, 52);
+
+    assert!(recommend(&Config::default(), &untouched, &[]).is_none());
+}
+
+#[test]
+fn warns_about_weak_signal() {
+    let untouched = window(&[0, 6, -6, 3]);
+    let touched = window(&[7, 8, 9]);
+
+    let rec = recommend(&Config::default(), &[&untouched], &[&touched]).unwrap();
+    assert_eq!(rec.proximity_threshold, ProximityThreshold::Counts10);
+    assert!(rec.warnings.contains(
+        TuningWarnings::NOISY | TuningWarnings::LOW_SNR | TuningWarnings::QUICK_RELEASE_UNCHANGED
+    ));
+    assert_eq!(rec.ch0_multipliers.sensitivity_multiplier(), 1);
+}